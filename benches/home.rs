@@ -1,5 +1,4 @@
-use criterion::{black_box, criterion_group, criterion_main, Benchmark, Criterion};
-use dirs;
+use criterion::{criterion_group, criterion_main, Criterion};
 use diskspace_insight::scan;
 
 fn scan_home(c: &mut Criterion) {
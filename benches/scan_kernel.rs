@@ -1,9 +1,6 @@
-use benchmark_sampledata;
 use criterion::*;
 use diskspace_insight::scan;
-use env_logger;
 use log::*;
-use std::fs::File;
 
 fn test_scan(c: &mut Criterion) {
     std::env::set_var("RUST_LOG", "INFO");
@@ -20,7 +17,7 @@ fn test_scan(c: &mut Criterion) {
         });
     });
 
-    sd.remove();
+    let _ = sd.remove();
 }
 
 criterion_group! {
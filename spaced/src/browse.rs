@@ -0,0 +1,427 @@
+//! An interactive, ncdu-style browser over a scan.
+
+use anyhow::Result;
+use bytesize::ByteSize;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, ClearType},
+};
+use diskspace_insight::{scan_with_options, DirInfo, ScanOptions};
+use std::collections::HashMap;
+use std::io::{stdout, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+#[cfg(test)]
+mod tests;
+
+const BAR_WIDTH: usize = 20;
+
+#[derive(Clone, Copy, PartialEq)]
+enum SortKey {
+    Size,
+    Name,
+    Modified,
+    Count,
+}
+
+/// A row in the listing: either a file or a subdirectory
+#[derive(Clone)]
+struct Entry {
+    name: String,
+    path: PathBuf,
+    is_dir: bool,
+    size: u64,
+    allocated: u64,
+    /// Number of files, including subdirectories
+    count: usize,
+    /// Latest modification, including subdirectories
+    modified: SystemTime,
+}
+
+/// The scan as seen by the browser, updated while scanning
+struct Scan {
+    /// Directory being browsed, so that the scanner only copies what is shown
+    current: PathBuf,
+    /// Listing of a directory while scanning, without file counts and latest changes
+    /// of subdirectories, which would need the whole tree
+    listing: Option<(PathBuf, Vec<Entry>)>,
+    combined_size: u64,
+    combined_allocated_size: u64,
+    done: Option<Finished>,
+}
+
+/// A completed scan
+struct Finished {
+    info: DirInfo,
+    /// Number of files and latest change of every directory, including subdirectories
+    stats: HashMap<PathBuf, (usize, SystemTime)>,
+}
+
+impl Finished {
+    fn new(info: DirInfo) -> Finished {
+        let mut stats: HashMap<PathBuf, (usize, SystemTime)> = HashMap::new();
+        for d in info.tree.values() {
+            let latest = d.files.iter().map(|f| f.modified).max();
+            for a in d.path.ancestors().take_while(|a| a.starts_with(&info.root)) {
+                let stat = stats
+                    .entry(a.to_path_buf())
+                    .or_insert((0, SystemTime::UNIX_EPOCH));
+                stat.0 += d.files.len();
+                stat.1 = stat.1.max(latest.unwrap_or(SystemTime::UNIX_EPOCH));
+            }
+        }
+        Finished { info, stats }
+    }
+}
+
+/// What the listing depends on, to only rebuild it when this changes
+#[derive(Clone, PartialEq)]
+struct View {
+    current: PathBuf,
+    sort: SortKey,
+    reverse: bool,
+    allocated: bool,
+}
+
+struct Browser {
+    root: PathBuf,
+    current: PathBuf,
+    selected: usize,
+    offset: usize,
+    sort: SortKey,
+    reverse: bool,
+    allocated: bool,
+    /// Entry to select once the listing is rebuilt, after going up a level
+    reselect: Option<PathBuf>,
+}
+
+/// Restores the terminal, even when returning early or panicking
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(stdout(), terminal::LeaveAlternateScreen, cursor::Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// List the subdirectories and files of `path`, unsorted
+fn listing(
+    info: &DirInfo,
+    path: &Path,
+    stats: Option<&HashMap<PathBuf, (usize, SystemTime)>>,
+) -> Vec<Entry> {
+    let dir = match info.tree.get(path) {
+        Some(dir) => dir,
+        None => return vec![],
+    };
+    let subdirs = dir.directories.iter().map(|path| {
+        let (count, modified) = stats
+            .and_then(|s| s.get(path))
+            .cloned()
+            .unwrap_or((0, SystemTime::UNIX_EPOCH));
+        let subdir = info.tree.get(path);
+        Entry {
+            name: format!("{}/", file_name(path)),
+            path: path.clone(),
+            is_dir: true,
+            size: subdir.map(|d| d.combined_size).unwrap_or_default(),
+            allocated: subdir
+                .map(|d| d.combined_allocated_size)
+                .unwrap_or_default(),
+            count,
+            modified,
+        }
+    });
+    let files = dir.files.iter().map(|f| Entry {
+        name: file_name(&f.path),
+        path: f.path.clone(),
+        is_dir: false,
+        size: f.size,
+        allocated: f.allocated,
+        count: 1,
+        modified: f.modified,
+    });
+    subdirs.chain(files).collect()
+}
+
+impl Browser {
+    fn view(&self) -> View {
+        View {
+            current: self.current.clone(),
+            sort: self.sort,
+            reverse: self.reverse,
+            allocated: self.allocated,
+        }
+    }
+
+    fn sort(&self, entries: &mut [Entry]) {
+        let allocated = self.allocated;
+        match self.sort {
+            SortKey::Size => entries.sort_by_key(|e| std::cmp::Reverse(e.shown_size(allocated))),
+            SortKey::Name => entries.sort_by(|a, b| a.name.cmp(&b.name)),
+            SortKey::Modified => entries.sort_by_key(|e| std::cmp::Reverse(e.modified)),
+            SortKey::Count => entries.sort_by_key(|e| std::cmp::Reverse(e.count)),
+        }
+        if self.reverse {
+            entries.reverse();
+        }
+    }
+
+    /// Sort by a key, or flip the order if already sorted by it
+    fn sort_by(&mut self, key: SortKey) {
+        if self.sort == key {
+            self.reverse = !self.reverse;
+        } else {
+            self.sort = key;
+            self.reverse = false;
+        }
+    }
+
+    fn draw(&mut self, scan: &Scan, entries: &[Entry]) -> Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+        let rows = height.saturating_sub(3);
+
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + rows {
+            self.offset = self.selected + 1 - rows;
+        }
+
+        let total = if self.allocated {
+            scan.combined_allocated_size
+        } else {
+            scan.combined_size
+        };
+        let largest = entries
+            .iter()
+            .map(|e| e.shown_size(self.allocated))
+            .max()
+            .unwrap_or_default()
+            .max(1);
+
+        let mut out = stdout();
+        queue!(out, cursor::MoveTo(0, 0))?;
+        let status = if scan.done.is_some() {
+            ""
+        } else {
+            " [scanning]"
+        };
+        let header = format!(
+            " {} | total {} ({}){}",
+            self.current.display(),
+            ByteSize(total),
+            if self.allocated {
+                "allocated"
+            } else {
+                "apparent"
+            },
+            status
+        );
+        queue!(
+            out,
+            Print(truncate(&header, width)),
+            terminal::Clear(ClearType::UntilNewLine)
+        )?;
+
+        for (i, entry) in entries.iter().enumerate().skip(self.offset).take(rows) {
+            let size = entry.shown_size(self.allocated);
+            let filled = (size as f64 / largest as f64 * BAR_WIDTH as f64).round() as usize;
+            let line = format!(
+                "{:>10} [{:<bar$}] {:>7} {}",
+                ByteSize(size).to_string(),
+                "#".repeat(filled),
+                entry.count,
+                entry.name,
+                bar = BAR_WIDTH
+            );
+            queue!(out, cursor::MoveTo(0, (i - self.offset + 1) as u16))?;
+            if i == self.selected {
+                queue!(out, SetAttribute(Attribute::Reverse))?;
+            }
+            queue!(
+                out,
+                Print(truncate(&line, width)),
+                SetAttribute(Attribute::Reset),
+                terminal::Clear(ClearType::UntilNewLine)
+            )?;
+        }
+        // Overwriting line by line instead of clearing the screen avoids flicker
+        let shown = entries.len().saturating_sub(self.offset).min(rows);
+        queue!(
+            out,
+            cursor::MoveTo(0, shown as u16 + 1),
+            terminal::Clear(ClearType::FromCursorDown)
+        )?;
+
+        let help = " ↑↓ move  → open  ← up  s/n/m/c sort size/name/mtime/count  a apparent/allocated  q quit";
+        queue!(
+            out,
+            cursor::MoveTo(0, height.saturating_sub(1) as u16),
+            Print(truncate(help, width))
+        )?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Handle a key, returning false to quit
+    fn key(&mut self, key: KeyEvent, entries: &[Entry]) -> bool {
+        let last = entries.len().saturating_sub(1);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.selected = (self.selected + 1).min(last),
+            KeyCode::PageUp => self.selected = self.selected.saturating_sub(20),
+            KeyCode::PageDown => self.selected = (self.selected + 20).min(last),
+            KeyCode::Home => self.selected = 0,
+            KeyCode::End => self.selected = last,
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
+                if let Some(entry) = entries.get(self.selected).filter(|e| e.is_dir) {
+                    self.current = entry.path.clone();
+                    self.selected = 0;
+                    self.offset = 0;
+                }
+            }
+            KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h')
+                if self.current != self.root =>
+            {
+                if let Some(parent) = self.current.parent().map(|p| p.to_path_buf()) {
+                    // Keep the directory we came from selected
+                    self.reselect = Some(std::mem::replace(&mut self.current, parent));
+                    self.offset = 0;
+                }
+            }
+            KeyCode::Char('s') => self.sort_by(SortKey::Size),
+            KeyCode::Char('n') => self.sort_by(SortKey::Name),
+            KeyCode::Char('m') => self.sort_by(SortKey::Modified),
+            KeyCode::Char('c') => self.sort_by(SortKey::Count),
+            KeyCode::Char('a') => self.allocated = !self.allocated,
+            _ => {}
+        }
+        true
+    }
+}
+
+impl Entry {
+    fn shown_size(&self, allocated: bool) -> u64 {
+        if allocated {
+            self.allocated
+        } else {
+            self.size
+        }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+fn truncate(s: &str, width: usize) -> String {
+    s.chars().take(width).collect()
+}
+
+/// Scan `path` with `options` in the background and browse it
+pub fn run(path: &Path, options: ScanOptions) -> Result<()> {
+    let scan = Arc::new(Mutex::new(Scan {
+        current: path.to_path_buf(),
+        listing: None,
+        combined_size: 0,
+        combined_allocated_size: 0,
+        done: None,
+    }));
+
+    let scanner = scan.clone();
+    let root = path.to_path_buf();
+    std::thread::spawn(move || {
+        let info = scan_with_options(
+            &root,
+            &options,
+            |info| {
+                // Only the shown directory is copied, not the whole tree
+                let mut scan = scanner.lock().unwrap();
+                let current = scan.current.clone();
+                scan.listing = Some((current.clone(), listing(info, &current, None)));
+                scan.combined_size = info.combined_size;
+                scan.combined_allocated_size = info.combined_allocated_size;
+            },
+            500,
+        );
+        let finished = Finished::new(info);
+        let mut scan = scanner.lock().unwrap();
+        scan.combined_size = finished.info.combined_size;
+        scan.combined_allocated_size = finished.info.combined_allocated_size;
+        scan.listing = None;
+        scan.done = Some(finished);
+    });
+
+    let mut browser = Browser {
+        root: path.to_path_buf(),
+        current: path.to_path_buf(),
+        selected: 0,
+        offset: 0,
+        sort: SortKey::Size,
+        reverse: false,
+        allocated: false,
+        reselect: None,
+    };
+
+    terminal::enable_raw_mode()?;
+    let _guard = TerminalGuard;
+    execute!(stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+
+    let mut entries: Vec<Entry> = vec![];
+    // The view the entries were listed for, once the scan is finished
+    let mut listed: Option<View> = None;
+    loop {
+        {
+            let mut scan = scan.lock().unwrap();
+            scan.current = browser.current.clone();
+            let view = browser.view();
+            match &scan.done {
+                Some(finished) => {
+                    if listed.as_ref() != Some(&view) {
+                        entries = listing(&finished.info, &browser.current, Some(&finished.stats));
+                        browser.sort(&mut entries);
+                        listed = Some(view);
+                    }
+                }
+                // Only the listing of one directory, so sorting it on every redraw is cheap
+                None => {
+                    entries = match &scan.listing {
+                        Some((path, listing)) if *path == browser.current => listing.clone(),
+                        _ => vec![],
+                    };
+                    browser.sort(&mut entries);
+                }
+            }
+            // The listing of a directory just entered may not be there yet while scanning
+            if !entries.is_empty() {
+                if let Some(previous) = browser.reselect.take() {
+                    browser.selected = entries
+                        .iter()
+                        .position(|e| e.path == previous)
+                        .unwrap_or_default();
+                }
+            }
+            browser.selected = browser.selected.min(entries.len().saturating_sub(1));
+            browser.draw(&scan, &entries)?;
+        }
+
+        if event::poll(Duration::from_millis(250))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !browser.key(key, &entries) {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use super::*;
+use crossterm::event::KeyModifiers;
+
+fn browser(root: &Path) -> Browser {
+    Browser {
+        root: root.to_path_buf(),
+        current: root.to_path_buf(),
+        selected: 0,
+        offset: 0,
+        sort: SortKey::Size,
+        reverse: false,
+        allocated: false,
+        reselect: None,
+    }
+}
+
+fn entry(name: &str, size: u64, allocated: u64, count: usize, age: u64) -> Entry {
+    Entry {
+        name: name.to_string(),
+        path: PathBuf::from(name),
+        is_dir: false,
+        size,
+        allocated,
+        count,
+        modified: SystemTime::UNIX_EPOCH + Duration::from_secs(age),
+    }
+}
+
+fn names(entries: &[Entry]) -> Vec<&str> {
+    entries.iter().map(|e| e.name.as_str()).collect()
+}
+
+fn press(browser: &mut Browser, c: char) -> bool {
+    browser.key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE), &[])
+}
+
+#[test]
+fn sorting() {
+    let mut entries = vec![
+        entry("b", 10, 4096, 1, 300),
+        entry("a", 300, 512, 5, 100),
+        entry("c", 20, 8192, 2, 200),
+    ];
+    let mut browser = browser(Path::new("root"));
+
+    browser.sort(&mut entries);
+    assert_eq!(names(&entries), vec!["a", "c", "b"]);
+
+    // Sorting by the same key again flips the order
+    assert!(press(&mut browser, 's'));
+    assert!(browser.reverse);
+    browser.sort(&mut entries);
+    assert_eq!(names(&entries), vec!["b", "c", "a"]);
+
+    // A new key starts in its natural order
+    press(&mut browser, 'n');
+    assert!(browser.sort == SortKey::Name && !browser.reverse);
+    browser.sort(&mut entries);
+    assert_eq!(names(&entries), vec!["a", "b", "c"]);
+
+    press(&mut browser, 'm');
+    browser.sort(&mut entries);
+    assert_eq!(names(&entries), vec!["b", "c", "a"]);
+
+    press(&mut browser, 'c');
+    browser.sort(&mut entries);
+    assert_eq!(names(&entries), vec!["a", "c", "b"]);
+    browser.sort_by(SortKey::Count);
+    browser.sort(&mut entries);
+    assert_eq!(names(&entries), vec!["b", "c", "a"]);
+
+    // Sizes on disk instead of apparent sizes
+    browser.sort_by(SortKey::Size);
+    press(&mut browser, 'a');
+    assert!(browser.allocated);
+    browser.sort(&mut entries);
+    assert_eq!(names(&entries), vec!["c", "b", "a"]);
+    assert_eq!(entries[0].shown_size(browser.allocated), 8192);
+    press(&mut browser, 'a');
+    browser.sort(&mut entries);
+    assert_eq!(names(&entries), vec!["a", "c", "b"]);
+    assert_eq!(entries[0].shown_size(browser.allocated), 300);
+
+    assert!(!press(&mut browser, 'q'));
+}
+
+#[test]
+fn listings() {
+    std::fs::create_dir_all("browsetest/sub/deeper").unwrap();
+    std::fs::write("browsetest/sub/one", vec![0; 100]).unwrap();
+    std::fs::write("browsetest/sub/deeper/two", vec![0; 200]).unwrap();
+    std::fs::write("browsetest/top", vec![0; 50]).unwrap();
+    let root = Path::new("browsetest");
+    let finished = Finished::new(diskspace_insight::scan(root));
+
+    let mut entries = listing(&finished.info, root, Some(&finished.stats));
+    let mut browser = browser(root);
+    browser.sort(&mut entries);
+    assert_eq!(names(&entries), vec!["sub/", "top"]);
+    let sub = &entries[0];
+    assert!(sub.is_dir);
+    assert_eq!(sub.path, root.join("sub"));
+    assert_eq!(sub.size, 300);
+    assert_eq!(sub.count, 2);
+    assert_eq!(sub.modified, finished.stats[&root.join("sub")].1);
+    let top = &entries[1];
+    assert!(!top.is_dir);
+    assert_eq!((top.size, top.count), (50, 1));
+
+    // While scanning there are no counts of subdirectories yet
+    let scanning = listing(&finished.info, root, None);
+    assert!(scanning.iter().any(|e| e.is_dir && e.count == 0));
+    assert!(listing(&finished.info, Path::new("browsetest/missing"), None).is_empty());
+
+    // Opening a directory and going back up selects it again
+    browser.key(KeyEvent::from(KeyCode::Enter), &entries);
+    assert_eq!(browser.current, root.join("sub"));
+    browser.key(KeyEvent::from(KeyCode::Left), &[]);
+    assert_eq!(browser.current, root);
+    assert_eq!(browser.reselect, Some(root.join("sub")));
+    // Not above the scanned directory
+    browser.key(KeyEvent::from(KeyCode::Left), &[]);
+    assert_eq!(browser.current, root);
+
+    std::fs::remove_dir_all("browsetest").unwrap();
+}
@@ -0,0 +1,624 @@
+use anyhow::{bail, Result};
+use bytesize::ByteSize;
+use clap::{Parser, Subcommand, ValueEnum};
+use diskspace_insight::dedupe::{dedupe, undo, DedupeOptions, KeepPolicy, LinkMethod};
+use diskspace_insight::exclude::DEFAULT_IGNORE_FILES;
+use diskspace_insight::export::{write_dirs, write_files, ExportFormat};
+use diskspace_insight::folded::write_folded;
+use diskspace_insight::html::{write_html_report, HtmlOptions};
+use diskspace_insight::overlap::MAX_DIRECTORIES;
+use diskspace_insight::prometheus::{write_prometheus, write_textfile, PrometheusOptions};
+use diskspace_insight::svg::{write_svg_treemap, ColorBy, SvgOptions};
+use diskspace_insight::{scan_archive, scan_with_options, DirInfo, ScanOptions};
+use serde_json::json;
+use std::fs::{File, OpenOptions};
+use std::io::{sink, stdout, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+mod browse;
+#[cfg(test)]
+mod tests;
+
+#[derive(Parser)]
+#[command(
+    name = "spaced",
+    version,
+    about = "Investigate where your diskspace has gone"
+)]
+struct Cli {
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = Format::Human, global = true)]
+    format: Format,
+    /// Number of entries to show
+    #[arg(short = 'n', long, default_value_t = 20, global = true)]
+    limit: usize,
+    /// Only show entries at most this many levels below the scanned path
+    #[arg(short, long, global = true)]
+    depth: Option<usize>,
+    /// Leave out files and directories matching this glob, in .gitignore syntax
+    #[arg(long, global = true)]
+    exclude: Vec<String>,
+    /// Only collect files matching this glob, in .gitignore syntax
+    #[arg(long, global = true)]
+    include: Vec<String>,
+    /// Honour .gitignore and .ignore files
+    #[arg(long, global = true)]
+    ignore_files: bool,
+    /// Stay on the filesystem of the scanned path
+    #[arg(short = 'x', long, global = true)]
+    one_file_system: bool,
+    /// Leave out pseudo filesystems such as proc and sysfs
+    #[arg(long, global = true)]
+    skip_pseudo: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show the largest files
+    Files { path: PathBuf },
+    /// Show the largest directories, including their subdirectories
+    Dirs { path: PathBuf },
+    /// Show the file types using the most space
+    Types { path: PathBuf },
+    /// Show duplicate files, by wasted space
+    Dupes { path: PathBuf },
+    /// Show directories with identical content, by wasted space
+    DupeDirs { path: PathBuf },
+    /// Show pairs of directories sharing files, with the files only one of them has
+    Overlap { path: PathBuf },
+    /// Show caches and build outputs that can be regenerated
+    Junk { path: PathBuf },
+    /// Show the space used on each filesystem mounted below the path
+    Filesystems { path: PathBuf },
+    /// Scan a zip archive instead of a directory
+    Archive {
+        path: PathBuf,
+        #[arg(value_enum, default_value_t = Report::Files)]
+        report: Report,
+    },
+    /// Replace duplicate files by links to one copy. Only shows what would be done unless --apply is given.
+    Dedupe {
+        path: PathBuf,
+        /// Which copy to keep
+        #[arg(long, value_enum, default_value_t = Keep::Shortest)]
+        keep: Keep,
+        /// Keep copies below these directories, in this order of preference, choosing among them by --keep
+        #[arg(long)]
+        prefer: Vec<PathBuf>,
+        /// Use copy-on-write clones instead of hard links
+        #[arg(long)]
+        reflink: bool,
+        /// Actually replace the copies
+        #[arg(long)]
+        apply: bool,
+        /// Log of replaced files, appended to
+        #[arg(long, default_value = "spaced-undo.jsonl")]
+        undo_log: PathBuf,
+    },
+    /// Turn files linked by dedupe back into independent copies
+    Undo {
+        /// Undo log written by dedupe
+        log: PathBuf,
+    },
+    /// Browse a directory interactively while it is being scanned
+    Browse { path: PathBuf },
+    /// Write a self-contained HTML report
+    Html {
+        path: PathBuf,
+        /// File to write to, instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Export every file or directory as CSV or JSON Lines
+    Export {
+        path: PathBuf,
+        #[arg(value_enum, default_value_t = Entries::Files)]
+        entries: Entries,
+        /// File to write to, instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Write JSON Lines instead of CSV
+        #[arg(long)]
+        jsonl: bool,
+    },
+    /// Write folded stacks for flamegraph tools, collapsed at --depth
+    Folded {
+        path: PathBuf,
+        /// File to write to, instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Write Prometheus metrics, for the node_exporter textfile collector
+    Metrics {
+        path: PathBuf,
+        /// File to replace atomically, instead of writing to standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Also report directories of at least this many bytes, at any depth
+        #[arg(long)]
+        min_size: Option<u64>,
+    },
+    /// Write a treemap as an SVG image
+    Svg {
+        path: PathBuf,
+        /// File to write to, instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[arg(long, default_value_t = 1280.0)]
+        width: f64,
+        #[arg(long, default_value_t = 800.0)]
+        height: f64,
+        /// Colour files by category instead of by extension
+        #[arg(long)]
+        by_category: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Report {
+    Files,
+    Dirs,
+    Types,
+    Dupes,
+    DupeDirs,
+    Overlap,
+    Junk,
+    Filesystems,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Keep {
+    Oldest,
+    Newest,
+    Shortest,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Entries {
+    Files,
+    Dirs,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    /// Aligned columns with human readable sizes
+    Human,
+    /// A JSON array, sizes in bytes
+    Json,
+    /// Tab separated values, sizes in bytes
+    Tsv,
+}
+
+/// A line of output
+struct Row {
+    size: u64,
+    name: String,
+    /// Number of files this row stands for, if it is more than one
+    count: Option<usize>,
+    /// Paths belonging to this row, such as the copies of a duplicate
+    paths: Vec<String>,
+}
+
+impl Row {
+    fn new(size: u64, name: String) -> Row {
+        Row {
+            size,
+            name,
+            count: None,
+            paths: vec![],
+        }
+    }
+}
+
+/// Levels below root. Files count as being on the level of their directory.
+fn depth(path: &Path, root: &Path) -> usize {
+    path.strip_prefix(root)
+        .map(|p| p.components().count())
+        .unwrap_or_default()
+}
+
+/// The rows of a report, with a line summing them up in human output
+struct Listing {
+    rows: Vec<Row>,
+    summary: Option<String>,
+}
+
+fn report(info: &DirInfo, root: &Path, report: Report, max_depth: Option<usize>) -> Listing {
+    let within_depth = |path: &Path| max_depth.map(|d| depth(path, root) <= d).unwrap_or(true);
+
+    let mut summary = None;
+    let rows = match report {
+        Report::Files => info
+            .files_by_size
+            .iter()
+            .filter(|f| within_depth(f.path.parent().unwrap_or(root)))
+            .map(|f| Row::new(f.size, f.path.display().to_string()))
+            .collect(),
+        Report::Dirs => {
+            let mut dirs: Vec<_> = info
+                .tree
+                .values()
+                .filter(|d| d.path.starts_with(root) && within_depth(&d.path))
+                .collect();
+            dirs.sort_by_key(|d| std::cmp::Reverse(d.combined_size));
+            dirs.iter()
+                .map(|d| Row::new(d.combined_size, d.path.display().to_string()))
+                .collect()
+        }
+        Report::Types => info
+            .types_by_size
+            .iter()
+            .map(|t| Row {
+                count: Some(t.files.len()),
+                ..Row::new(t.size, t.ext.clone())
+            })
+            .collect(),
+        Report::Dupes | Report::DupeDirs => {
+            let duplicates = match report {
+                Report::Dupes => info.duplicate_report(),
+                _ => info.duplicate_directories(),
+            };
+            summary = Some(format!(
+                "{:>10}  reclaimable in {} groups",
+                ByteSize(duplicates.reclaimable),
+                duplicates.groups.len()
+            ));
+            duplicates
+                .groups
+                .into_iter()
+                .map(|g| Row {
+                    count: Some(g.count),
+                    paths: g.paths.iter().map(|p| p.display().to_string()).collect(),
+                    ..Row::new(g.wasted, ByteSize(g.size).to_string())
+                })
+                .collect()
+        }
+        Report::Overlap => {
+            let overlaps = info.overlapping_directories();
+            let widespread = &overlaps.widespread;
+            if !widespread.is_empty() {
+                summary = Some(format!(
+                    "{:>10}  in {} files found in more than {} directories, left out",
+                    ByteSize(widespread.iter().map(|g| g.size).sum()),
+                    widespread.len(),
+                    MAX_DIRECTORIES
+                ));
+            }
+            overlaps
+                .overlaps
+                .into_iter()
+                .map(|o| {
+                    // Marked like a diff of the two directories
+                    let mut paths = vec![];
+                    for (marker, listed, count) in [
+                        ("<", &o.only_first, o.only_first_count),
+                        (">", &o.only_second, o.only_second_count),
+                    ] {
+                        paths.extend(listed.iter().map(|p| format!("{} {}", marker, p.display())));
+                        if count > listed.len() {
+                            paths.push(format!("{} and {} more", marker, count - listed.len()));
+                        }
+                    }
+                    Row {
+                        count: Some(o.shared_count),
+                        paths,
+                        ..Row::new(
+                            o.shared_size,
+                            format!("{} and {}", o.first.display(), o.second.display()),
+                        )
+                    }
+                })
+                .collect()
+        }
+        Report::Junk => info
+            .junk()
+            .into_iter()
+            .map(|m| Row {
+                paths: m.regenerate.into_iter().collect(),
+                ..Row::new(
+                    m.size,
+                    format!(
+                        "{} ({}, {} confidence)",
+                        m.path.display(),
+                        m.rule,
+                        m.confidence
+                    ),
+                )
+            })
+            .collect(),
+        Report::Filesystems => info
+            .filesystem_usage()
+            .into_iter()
+            .map(|u| {
+                let fs_type = u.fs_type.unwrap_or_else(|| "unknown".to_string());
+                Row::new(u.size, format!("{} ({})", u.path.display(), fs_type))
+            })
+            .collect(),
+    };
+    Listing { rows, summary }
+}
+
+fn print(rows: &[Row], format: Format) -> Result<()> {
+    match format {
+        Format::Human => {
+            for row in rows {
+                match row.count {
+                    Some(count) => {
+                        println!("{:>10}  {} ({} files)", ByteSize(row.size), row.name, count)
+                    }
+                    None => println!("{:>10}  {}", ByteSize(row.size), row.name),
+                }
+                for path in &row.paths {
+                    println!("{:>10}  {}", "", path);
+                }
+            }
+        }
+        Format::Tsv => {
+            for row in rows {
+                let mut fields = vec![
+                    row.size.to_string(),
+                    row.count.map(|c| c.to_string()).unwrap_or_default(),
+                    row.name.clone(),
+                ];
+                fields.extend(row.paths.iter().cloned());
+                println!("{}", fields.join("\t"));
+            }
+        }
+        Format::Json => {
+            let rows: Vec<_> = rows
+                .iter()
+                .map(|row| {
+                    let mut value = json!({ "name": row.name, "size": row.size });
+                    if let Some(count) = row.count {
+                        value["count"] = json!(count);
+                    }
+                    if !row.paths.is_empty() {
+                        value["paths"] = json!(row.paths);
+                    }
+                    value
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&rows)?);
+        }
+    }
+    Ok(())
+}
+
+impl Cli {
+    /// The exclusion rules given on the command line
+    fn scan_options(&self) -> ScanOptions {
+        ScanOptions {
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+            ignore_files: if self.ignore_files {
+                DEFAULT_IGNORE_FILES.iter().map(|s| s.to_string()).collect()
+            } else {
+                vec![]
+            },
+            one_file_system: self.one_file_system,
+            skip_pseudo_filesystems: self.skip_pseudo,
+            ..Default::default()
+        }
+    }
+
+    /// Scan a directory with the exclusion rules given on the command line
+    fn scan(&self, path: &Path) -> DirInfo {
+        let info = scan_with_options(path, &self.scan_options(), |_| {}, u128::MAX);
+        if !info.skipped.is_empty() {
+            eprintln!("Skipped {} paths", info.skipped.len());
+        }
+        info
+    }
+
+    /// Scan a path, failing if it does not exist
+    fn scan_existing(&self, path: &Path) -> Result<DirInfo> {
+        if !path.exists() {
+            bail!("{} does not exist", path.display());
+        }
+        Ok(self.scan(path))
+    }
+}
+
+/// Scan a directory or archive and print a report
+fn run_report(cli: &Cli, path: &Path, kind: Report, archive: bool) -> Result<()> {
+    let (info, root) = if archive {
+        // Archives are read whole, nothing in them is left out
+        if !cli.include.is_empty()
+            || !cli.exclude.is_empty()
+            || cli.ignore_files
+            || cli.one_file_system
+            || cli.skip_pseudo
+        {
+            bail!("exclusion rules such as --exclude do not apply to archives");
+        }
+        if !path.is_file() {
+            bail!("{} is not a file", path.display());
+        }
+        (scan_archive(path)?, PathBuf::new())
+    } else {
+        (cli.scan_existing(path)?, path.to_path_buf())
+    };
+
+    let mut listing = report(&info, &root, kind, cli.depth);
+    listing.rows.truncate(cli.limit);
+    print(&listing.rows, cli.format)?;
+    if let Some(summary) = listing.summary.filter(|_| cli.format == Format::Human) {
+        println!("{}", summary);
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let cli = Cli::parse();
+
+    match &cli.command {
+        Command::Files { path } => run_report(&cli, path, Report::Files, false),
+        Command::Dirs { path } => run_report(&cli, path, Report::Dirs, false),
+        Command::Types { path } => run_report(&cli, path, Report::Types, false),
+        Command::Dupes { path } => run_report(&cli, path, Report::Dupes, false),
+        Command::DupeDirs { path } => run_report(&cli, path, Report::DupeDirs, false),
+        Command::Overlap { path } => run_report(&cli, path, Report::Overlap, false),
+        Command::Junk { path } => run_report(&cli, path, Report::Junk, false),
+        Command::Filesystems { path } => run_report(&cli, path, Report::Filesystems, false),
+        Command::Archive { path, report } => run_report(&cli, path, *report, true),
+        Command::Dedupe {
+            path,
+            keep,
+            prefer,
+            reflink,
+            apply,
+            undo_log,
+        } => {
+            let info = cli.scan_existing(path)?;
+            let options = DedupeOptions {
+                keep: match keep {
+                    Keep::Oldest => KeepPolicy::Oldest,
+                    Keep::Newest => KeepPolicy::Newest,
+                    Keep::Shortest => KeepPolicy::ShortestPath,
+                },
+                prefer: prefer.clone(),
+                method: if *reflink {
+                    LinkMethod::Reflink
+                } else {
+                    LinkMethod::Hardlink
+                },
+                dry_run: !apply,
+            };
+            let log: Box<dyn Write> = if *apply {
+                Box::new(
+                    OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(undo_log)?,
+                )
+            } else {
+                Box::new(sink())
+            };
+            let outcome = dedupe(&info.duplicate_report().groups, &options, log)?;
+            for action in &outcome.actions {
+                println!(
+                    "{:>10}  {} -> {}",
+                    ByteSize(action.size),
+                    action.replaced.display(),
+                    action.kept.display()
+                );
+            }
+            for (path, reason) in &outcome.skipped {
+                eprintln!("Skipped {}: {}", path.display(), reason);
+            }
+            println!(
+                "{:>10}  {} by {} files",
+                ByteSize(outcome.saved()),
+                if *apply { "saved" } else { "would be saved" },
+                outcome.actions.len()
+            );
+            Ok(())
+        }
+        Command::Undo { log } => {
+            let restored = undo(File::open(log)?)?;
+            println!("Restored {} files", restored);
+            Ok(())
+        }
+        Command::Browse { path } => {
+            if !path.is_dir() {
+                bail!("{} is not a directory", path.display());
+            }
+            browse::run(path, cli.scan_options())
+        }
+        Command::Html { path, output } => {
+            let info = cli.scan_existing(path)?;
+            let mut options = HtmlOptions {
+                top: cli.limit,
+                ..Default::default()
+            };
+            if let Some(depth) = cli.depth {
+                options.max_depth = depth;
+            }
+            write_output(output.as_deref(), |w| {
+                write_html_report(&info, path, &options, w)
+            })
+        }
+        Command::Export {
+            path,
+            entries,
+            output,
+            jsonl,
+        } => {
+            let info = cli.scan_existing(path)?;
+            let format = if *jsonl {
+                ExportFormat::JsonLines
+            } else {
+                ExportFormat::Csv
+            };
+            write_output(output.as_deref(), |w| match entries {
+                Entries::Files => write_files(&info, path, format, w),
+                Entries::Dirs => write_dirs(&info, path, format, w),
+            })
+        }
+        Command::Folded { path, output } => {
+            let info = cli.scan_existing(path)?;
+            write_output(output.as_deref(), |w| {
+                write_folded(&info, path, cli.depth, w)
+            })
+        }
+        Command::Metrics {
+            path,
+            output,
+            min_size,
+        } => {
+            let info = cli.scan_existing(path)?;
+            let mut options = PrometheusOptions {
+                min_size: *min_size,
+                ..Default::default()
+            };
+            if let Some(depth) = cli.depth {
+                options.max_depth = depth;
+            }
+            match output {
+                Some(output) => write_textfile(&info, path, &options, output),
+                None => write_output(None, |w| write_prometheus(&info, path, &options, w)),
+            }
+        }
+        Command::Svg {
+            path,
+            output,
+            width,
+            height,
+            by_category,
+        } => {
+            let info = cli.scan_existing(path)?;
+            let mut options = SvgOptions {
+                width: *width,
+                height: *height,
+                ..Default::default()
+            };
+            if *by_category {
+                options.color_by = ColorBy::Category;
+            }
+            if let Some(depth) = cli.depth {
+                options.max_depth = depth;
+            }
+            write_output(output.as_deref(), |w| {
+                write_svg_treemap(&info, path, &options, w)
+            })
+        }
+    }
+}
+
+/// Write to the output file, or standard output if none is given. The output is
+/// flushed here, as errors of a buffered writer that is only dropped are lost.
+fn write_output<F>(path: Option<&Path>, write: F) -> Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> Result<()>,
+{
+    let mut output: BufWriter<Box<dyn Write>> = BufWriter::new(match path {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(stdout()),
+    });
+    write(&mut output)?;
+    output.flush()?;
+    Ok(())
+}
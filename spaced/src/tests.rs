@@ -0,0 +1,121 @@
+use super::*;
+use clap::CommandFactory;
+
+fn parse(args: &[&str]) -> Cli {
+    Cli::try_parse_from(args).unwrap()
+}
+
+#[test]
+fn arguments() {
+    Cli::command().debug_assert();
+
+    // Global options may follow the subcommand
+    let cli = parse(&["spaced", "dirs", "some/path", "-n", "5", "--format", "json"]);
+    assert_eq!(cli.limit, 5);
+    assert!(cli.format == Format::Json);
+    assert!(matches!(&cli.command, Command::Dirs { path } if path == Path::new("some/path")));
+
+    let cli = parse(&[
+        "spaced", "dedupe", "x", "--keep", "newest", "--prefer", "a", "--prefer", "b",
+    ]);
+    match &cli.command {
+        Command::Dedupe {
+            keep,
+            prefer,
+            apply,
+            undo_log,
+            ..
+        } => {
+            assert!(matches!(keep, Keep::Newest));
+            assert_eq!(prefer, &[PathBuf::from("a"), PathBuf::from("b")]);
+            assert!(!apply);
+            assert_eq!(undo_log, Path::new("spaced-undo.jsonl"));
+        }
+        _ => panic!("not parsed as dedupe"),
+    }
+
+    let cli = parse(&["spaced", "archive", "a.zip", "dupe-dirs"]);
+    assert!(matches!(
+        cli.command,
+        Command::Archive {
+            report: Report::DupeDirs,
+            ..
+        }
+    ));
+    let cli = parse(&[
+        "spaced",
+        "-x",
+        "--exclude",
+        "*.o",
+        "--exclude",
+        "target/",
+        "files",
+        ".",
+    ]);
+    assert!(cli.one_file_system);
+    assert_eq!(cli.exclude, vec!["*.o", "target/"]);
+
+    let options = cli.scan_options();
+    assert_eq!(options.exclude, vec!["*.o", "target/"]);
+    assert!(options.one_file_system && !options.skip_pseudo_filesystems);
+    assert!(options.ignore_files.is_empty());
+
+    // Archives are read whole, so exclusion rules are refused
+    let cli = parse(&["spaced", "--exclude", "*.o", "archive", "a.zip"]);
+    let err = run_report(&cli, Path::new("a.zip"), Report::Files, true).unwrap_err();
+    assert!(err.to_string().contains("do not apply to archives"));
+
+    assert!(Cli::try_parse_from(["spaced", "dirs"]).is_err());
+    assert!(Cli::try_parse_from(["spaced", "--format", "xml", "dirs", "."]).is_err());
+    assert!(Cli::try_parse_from(["spaced", "dedupe", ".", "--keep", "largest"]).is_err());
+}
+
+#[test]
+fn reports() {
+    std::fs::create_dir_all("reporttest/a/b").unwrap();
+    std::fs::write("reporttest/a/b/big.txt", vec![0; 300]).unwrap();
+    std::fs::write("reporttest/a/one.rs", b"same").unwrap();
+    std::fs::write("reporttest/two.rs", b"same").unwrap();
+    let root = Path::new("reporttest");
+
+    let cli = parse(&["spaced", "files", "reporttest"]);
+    assert!(cli.scan_existing(Path::new("reporttest/missing")).is_err());
+    let info = cli.scan_existing(root).unwrap();
+
+    let files = report(&info, root, Report::Files, None).rows;
+    assert_eq!(files.len(), 3);
+    assert_eq!(files[0].name, "reporttest/a/b/big.txt");
+    assert_eq!(files[0].size, 300);
+    assert_eq!(report(&info, root, Report::Files, Some(0)).rows.len(), 1);
+
+    assert!(report(&info, root, Report::Dirs, None).summary.is_none());
+    let dirs = report(&info, root, Report::Dirs, Some(1)).rows;
+    let names: Vec<&str> = dirs.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, vec!["reporttest", "reporttest/a"]);
+
+    let dupes = report(&info, root, Report::Dupes, None);
+    assert!(dupes.summary.unwrap().ends_with("reclaimable in 1 groups"));
+    let dupes = dupes.rows;
+    assert_eq!(dupes.len(), 1);
+    assert_eq!(dupes[0].count, Some(2));
+    assert_eq!(dupes[0].paths.len(), 2);
+
+    let types = report(&info, root, Report::Types, None).rows;
+    assert_eq!(types[0].name, "txt");
+    assert_eq!(types[1].count, Some(2));
+
+    // Every report runs on a plain directory
+    for kind in Report::value_variants() {
+        report(&info, root, *kind, cli.depth);
+    }
+
+    write_output(Some(Path::new("reporttest/folded.txt")), |w| {
+        write_folded(&info, root, None, w)
+    })
+    .unwrap();
+    let folded = std::fs::read_to_string("reporttest/folded.txt").unwrap();
+    assert!(folded.contains("reporttest;a;b;big.txt 300\n"));
+    assert!(write_output(Some(Path::new("reporttest/missing/out.txt")), |_| Ok(())).is_err());
+
+    std::fs::remove_dir_all("reporttest").unwrap();
+}
@@ -0,0 +1,187 @@
+//! Age analysis: how many bytes were last modified in a given time span.
+
+use crate::{DirInfo, Directory, File, FileType};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+const DAY: Duration = Duration::from_secs(60 * 60 * 24);
+
+#[derive(Debug, Clone)]
+/// The buckets an age report is made of.
+/// Files older than the last limit end up in an additional "older" bucket.
+pub struct AgeBuckets {
+    /// Label and maximum age of each bucket, ascending
+    pub limits: Vec<(String, Duration)>,
+    /// The point in time ages are measured from
+    pub reference: SystemTime,
+}
+
+impl Default for AgeBuckets {
+    fn default() -> Self {
+        AgeBuckets::new(vec![
+            ("day".to_string(), DAY),
+            ("week".to_string(), DAY * 7),
+            ("month".to_string(), DAY * 30),
+            ("year".to_string(), DAY * 365),
+        ])
+    }
+}
+
+impl AgeBuckets {
+    /// Construct buckets from labelled age limits, measured from now
+    pub fn new(mut limits: Vec<(String, Duration)>) -> AgeBuckets {
+        limits.sort_by_key(|(_, d)| *d);
+        AgeBuckets {
+            limits,
+            reference: SystemTime::now(),
+        }
+    }
+
+    /// Age of a file, relative to the reference time. Files from the future are zero days old.
+    pub fn age_of(&self, file: &File) -> Duration {
+        self.reference
+            .duration_since(file.modified)
+            .unwrap_or_default()
+    }
+
+    /// Index of the bucket a file falls into
+    pub fn index_of(&self, file: &File) -> usize {
+        let age = self.age_of(file);
+        self.limits
+            .iter()
+            .position(|(_, limit)| age <= *limit)
+            .unwrap_or(self.limits.len())
+    }
+
+    /// Sort files into buckets
+    pub fn report<'a, I: IntoIterator<Item = &'a File>>(&self, files: I) -> AgeReport {
+        let mut buckets: Vec<AgeBucket> = self
+            .limits
+            .iter()
+            .map(|(label, limit)| AgeBucket {
+                label: label.clone(),
+                max_age: Some(*limit),
+                ..Default::default()
+            })
+            .collect();
+        buckets.push(AgeBucket {
+            label: "older".to_string(),
+            ..Default::default()
+        });
+
+        for file in files {
+            let bucket = &mut buckets[self.index_of(file)];
+            bucket.size += file.size;
+            bucket.count += 1;
+        }
+        AgeReport { buckets }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Bytes and files last modified within an age span
+pub struct AgeBucket {
+    pub label: String,
+    /// The upper age limit, `None` for the "older" bucket
+    pub max_age: Option<Duration>,
+    pub size: u64,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Bytes and file counts grouped by last modification
+pub struct AgeReport {
+    /// All buckets, youngest first
+    pub buckets: Vec<AgeBucket>,
+}
+
+impl AgeReport {
+    /// Combined size of all buckets
+    pub fn size(&self) -> u64 {
+        self.buckets.iter().map(|b| b.size).sum()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// A directory and the amount of data in it that has not been touched for a while
+pub struct StaleDirectory {
+    pub path: PathBuf,
+    /// Bytes in this subtree older than the requested age
+    pub stale_size: u64,
+    /// Bytes in this subtree
+    pub combined_size: u64,
+}
+
+impl DirInfo {
+    /// Return an age report over all files
+    pub fn age_report(&self, buckets: &AgeBuckets) -> AgeReport {
+        buckets.report(&self.files)
+    }
+
+    /// Return directories by the amount of data not modified for longer than
+    /// `older_than` before `reference`, usually `SystemTime::now()`
+    pub fn dirs_by_stale_size(
+        &self,
+        older_than: Duration,
+        reference: SystemTime,
+    ) -> Vec<StaleDirectory> {
+        let mut stale: HashMap<PathBuf, u64> = HashMap::new();
+
+        for dir in self.tree.values() {
+            let size: u64 = dir
+                .files
+                .iter()
+                .filter(|f| reference.duration_since(f.modified).unwrap_or_default() > older_than)
+                .map(|f| f.size)
+                .sum();
+            if size == 0 {
+                continue;
+            }
+            // Add to this directory and all of its ancestors within the scan
+            for a in dir.path.ancestors() {
+                match self.tree.get(a) {
+                    Some(d) if d.combined_size > 0 => {
+                        *stale.entry(a.to_path_buf()).or_default() += size;
+                    }
+                    _ => break,
+                }
+            }
+        }
+
+        let mut dirs: Vec<StaleDirectory> = stale
+            .into_iter()
+            .map(|(path, stale_size)| StaleDirectory {
+                combined_size: self
+                    .tree
+                    .get(&path)
+                    .map(|d| d.combined_size)
+                    .unwrap_or_default(),
+                path,
+                stale_size,
+            })
+            .collect();
+        // Ties go to the outermost directory
+        dirs.par_sort_by(|a, b| {
+            b.stale_size
+                .cmp(&a.stale_size)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        dirs
+    }
+}
+
+impl Directory {
+    /// Return an age report over this directory and all subdirectories
+    pub fn age_report(&self, info: &DirInfo, buckets: &AgeBuckets) -> AgeReport {
+        buckets.report(info.subtree_files(self))
+    }
+}
+
+impl FileType {
+    /// Return an age report over all files of this type
+    pub fn age_report(&self, buckets: &AgeBuckets) -> AgeReport {
+        buckets.report(&self.files)
+    }
+}
@@ -0,0 +1,53 @@
+//! Support for the Cache Directory Tagging specification: directories containing a
+//! `CACHEDIR.TAG` file that starts with a fixed signature hold regenerable data.
+//! See <https://bford.info/cachedir/>.
+
+use crate::{DirInfo, Directory};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+pub const TAG_NAME: &str = "CACHEDIR.TAG";
+pub const SIGNATURE: &[u8] = b"Signature: 8a477f597d28d172789f06886806bc55";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// What a scan does with tagged directories
+pub enum CacheDirs {
+    /// Scan them like any other directory
+    #[default]
+    Ignore,
+    /// Scan them and set `Directory::cache`
+    Label,
+    /// Leave them out, and list them in `DirInfo::skipped`
+    Exclude,
+}
+
+/// Return true if `dir` contains a `CACHEDIR.TAG` with a valid signature
+pub fn is_tagged(dir: &Path) -> bool {
+    let mut buf = [0; SIGNATURE.len()];
+    fs::File::open(dir.join(TAG_NAME))
+        .and_then(|mut f| f.read_exact(&mut buf))
+        .map(|_| buf == SIGNATURE)
+        .unwrap_or(false)
+}
+
+impl DirInfo {
+    /// Return directories labelled as cache, outermost first
+    pub fn cache_dirs(&self) -> Vec<&Directory> {
+        let mut dirs: Vec<&Directory> = self.tree.values().filter(|d| d.cache).collect();
+        dirs.sort_by(|a, b| a.path.cmp(&b.path));
+        dirs
+    }
+
+    /// Return the combined size of all directories labelled as cache,
+    /// counting caches inside caches once
+    pub fn cache_size(&self) -> u64 {
+        let mut outer: Vec<&Directory> = vec![];
+        for dir in self.cache_dirs() {
+            if !outer.iter().any(|o| dir.path.starts_with(&o.path)) {
+                outer.push(dir);
+            }
+        }
+        outer.iter().map(|d| d.combined_size).sum()
+    }
+}
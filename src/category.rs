@@ -0,0 +1,216 @@
+//! Categories group file types into a handful of buckets, such as videos or archives.
+
+use crate::{DirInfo, Directory, File};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// A broad kind of file
+pub enum Category {
+    Video,
+    Image,
+    Audio,
+    Archive,
+    Document,
+    Code,
+    BuildArtifact,
+    /// A user defined category
+    Custom(String),
+    /// Everything not covered by the table
+    Other,
+}
+
+impl std::fmt::Display for Category {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Category::Video => write!(f, "Video"),
+            Category::Image => write!(f, "Images"),
+            Category::Audio => write!(f, "Audio"),
+            Category::Archive => write!(f, "Archives"),
+            Category::Document => write!(f, "Documents"),
+            Category::Code => write!(f, "Code"),
+            Category::BuildArtifact => write!(f, "Build artifacts"),
+            Category::Custom(name) => write!(f, "{}", name),
+            Category::Other => write!(f, "Other"),
+        }
+    }
+}
+
+const BUILTIN_EXTENSIONS: &[(Category, &[&str])] = &[
+    (
+        Category::Video,
+        &[
+            "mp4", "mkv", "avi", "mov", "wmv", "flv", "webm", "m4v", "mpg", "mpeg", "3gp",
+        ],
+    ),
+    (
+        Category::Image,
+        &[
+            "jpg", "jpeg", "png", "gif", "bmp", "tif", "tiff", "webp", "heic", "svg", "ico", "psd",
+            "raw", "cr2", "nef", "dng", "exr", "hdr",
+        ],
+    ),
+    (
+        Category::Audio,
+        &[
+            "mp3", "flac", "wav", "ogg", "opus", "m4a", "aac", "wma", "aiff",
+        ],
+    ),
+    (
+        Category::Archive,
+        &[
+            "zip", "tar", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar", "iso", "dmg", "deb", "rpm",
+            "jar", "appimage",
+        ],
+    ),
+    (
+        Category::Document,
+        &[
+            "pdf", "doc", "docx", "odt", "xls", "xlsx", "ods", "ppt", "pptx", "odp", "txt", "md",
+            "rtf", "epub", "csv",
+        ],
+    ),
+    (
+        Category::Code,
+        &[
+            "rs", "c", "h", "cpp", "hpp", "cc", "py", "js", "ts", "jsx", "tsx", "java", "kt", "go",
+            "rb", "php", "cs", "swift", "sh", "toml", "json", "yaml", "yml", "xml", "html", "css",
+        ],
+    ),
+    (
+        Category::BuildArtifact,
+        &[
+            "o", "obj", "a", "lib", "so", "dll", "dylib", "rlib", "rmeta", "d", "class", "pyc",
+            "pdb", "exe", "elf", "wasm",
+        ],
+    ),
+];
+
+const BUILTIN_MIME_PREFIXES: &[(&str, Category)] = &[
+    ("video/", Category::Video),
+    ("image/", Category::Image),
+    ("audio/", Category::Audio),
+    ("application/zip", Category::Archive),
+    ("application/gzip", Category::Archive),
+    ("application/x-tar", Category::Archive),
+    ("application/x-xz", Category::Archive),
+    ("application/x-bzip2", Category::Archive),
+    ("application/x-7z-compressed", Category::Archive),
+    ("application/zstd", Category::Archive),
+    ("application/pdf", Category::Document),
+    ("text/x-script", Category::Code),
+    ("application/x-object", Category::BuildArtifact),
+    ("application/x-executable", Category::BuildArtifact),
+];
+
+/// The built-in category of an extension, if it has one
+pub fn builtin_category(ext: &str) -> Option<Category> {
+    BUILTIN_EXTENSIONS
+        .iter()
+        .find(|(_, exts)| exts.contains(&ext))
+        .map(|(category, _)| category.clone())
+}
+
+#[derive(Debug, Clone)]
+/// Maps extensions and MIME types to categories. Extensions take precedence.
+pub struct CategoryTable {
+    /// Lowercase extension to category
+    pub extensions: HashMap<String, Category>,
+    /// MIME type prefix to category, first match wins
+    pub mime_prefixes: Vec<(String, Category)>,
+}
+
+impl Default for CategoryTable {
+    fn default() -> Self {
+        let mut table = CategoryTable::empty();
+        for (category, exts) in BUILTIN_EXTENSIONS {
+            for ext in exts.iter() {
+                table.insert_ext(ext, category.clone());
+            }
+        }
+        for (prefix, category) in BUILTIN_MIME_PREFIXES {
+            table.insert_mime(prefix, category.clone());
+        }
+        table
+    }
+}
+
+impl CategoryTable {
+    /// A table without any rules
+    pub fn empty() -> CategoryTable {
+        CategoryTable {
+            extensions: HashMap::new(),
+            mime_prefixes: vec![],
+        }
+    }
+
+    /// Assign an extension to a category, replacing an existing rule
+    pub fn insert_ext(&mut self, ext: &str, category: Category) {
+        self.extensions.insert(ext.to_lowercase(), category);
+    }
+
+    /// Assign MIME types starting with `prefix` to a category.
+    /// Rules added later are checked first.
+    pub fn insert_mime(&mut self, prefix: &str, category: Category) {
+        self.mime_prefixes.insert(0, (prefix.to_string(), category));
+    }
+
+    /// Return the category of a file
+    pub fn category_of(&self, file: &File) -> Category {
+        file.type_ext()
+            .and_then(|ext| self.extensions.get(ext))
+            .or_else(|| {
+                let mime = file.mime.as_ref()?;
+                self.mime_prefixes
+                    .iter()
+                    .find(|(prefix, _)| mime.starts_with(prefix.as_str()))
+                    .map(|(_, category)| category)
+            })
+            .cloned()
+            .unwrap_or(Category::Other)
+    }
+
+    /// Sum up files by category, largest first
+    pub fn categorize<'a, I: IntoIterator<Item = &'a File>>(&self, files: I) -> Vec<CategoryUsage> {
+        let mut usage: HashMap<Category, CategoryUsage> = HashMap::new();
+        for file in files {
+            let category = self.category_of(file);
+            let u = usage.entry(category.clone()).or_insert(CategoryUsage {
+                category,
+                size: 0,
+                count: 0,
+            });
+            u.size += file.size;
+            u.count += 1;
+        }
+        let mut usage: Vec<CategoryUsage> = usage.into_values().collect();
+        usage.sort_by(|a, b| b.size.cmp(&a.size).then(a.category.cmp(&b.category)));
+        usage
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Bytes and files in a category
+pub struct CategoryUsage {
+    pub category: Category,
+    pub size: u64,
+    pub count: usize,
+}
+
+impl DirInfo {
+    /// Return categories using the built-in table, ordered by size
+    pub fn categories_by_size(&self) -> Vec<CategoryUsage> {
+        self.categories_by_size_with(&CategoryTable::default())
+    }
+
+    /// Return categories using a custom table, ordered by size
+    pub fn categories_by_size_with(&self, table: &CategoryTable) -> Vec<CategoryUsage> {
+        table.categorize(&self.files)
+    }
+}
+
+impl Directory {
+    /// Return categories of this directory and all subdirectories, ordered by size
+    pub fn categories_by_size(&self, info: &DirInfo, table: &CategoryTable) -> Vec<CategoryUsage> {
+        table.categorize(info.subtree_files(self))
+    }
+}
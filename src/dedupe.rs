@@ -0,0 +1,487 @@
+//! Replace duplicate files by links to a single copy.
+//!
+//! Nothing is changed unless `DedupeOptions::dry_run` is turned off. Every file is compared
+//! byte by byte with the copy that is kept before it is replaced, as the scan only compared
+//! hashes and files may have changed since. Each replacement is recorded in an undo log,
+//! one JSON object per line, which [`undo`] reads to turn the links back into independent copies.
+//! The log holds absolute paths, as strings or, if they are not valid UTF-8, as arrays of bytes.
+//! Entries are written before the file is replaced, so a crash never leaves a replaced file
+//! without one. A replacement that fails is followed by a line marking its entry as not applied.
+
+use crate::duplicates::DuplicateGroup;
+use anyhow::{bail, Result};
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Which copy of a group is kept
+pub enum KeepPolicy {
+    /// The least recently modified copy
+    Oldest,
+    /// The most recently modified copy
+    Newest,
+    /// The copy with the fewest path components, then the shortest path
+    ShortestPath,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How copies are replaced
+pub enum LinkMethod {
+    /// A hard link to the kept file. All copies then share permissions and timestamps.
+    Hardlink,
+    /// A copy-on-write clone, which is an independent file sharing the data on disk.
+    /// Only supported on Linux, by filesystems such as btrfs and xfs.
+    Reflink,
+}
+
+impl LinkMethod {
+    fn name(&self) -> &'static str {
+        match self {
+            LinkMethod::Hardlink => "hardlink",
+            LinkMethod::Reflink => "reflink",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DedupeOptions {
+    pub keep: KeepPolicy,
+    /// Directories in order of preference. Copies below the earliest of them are kept
+    /// over all others, choosing among them by `keep`.
+    pub prefer: Vec<PathBuf>,
+    pub method: LinkMethod,
+    /// Only report what would be done
+    pub dry_run: bool,
+}
+
+impl Default for DedupeOptions {
+    fn default() -> Self {
+        DedupeOptions {
+            keep: KeepPolicy::ShortestPath,
+            prefer: vec![],
+            method: LinkMethod::Hardlink,
+            dry_run: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A copy that was, or in a dry run would be, replaced
+pub struct DedupeAction {
+    pub kept: PathBuf,
+    pub replaced: PathBuf,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DedupeOutcome {
+    pub actions: Vec<DedupeAction>,
+    /// Copies left alone, with the reason
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+impl DedupeOutcome {
+    /// Space freed by all actions
+    pub fn saved(&self) -> u64 {
+        self.actions.iter().map(|a| a.size).sum()
+    }
+}
+
+/// Replace all but one copy of every group, recording each replacement in `undo_log`
+pub fn dedupe<'a, I, W>(
+    groups: I,
+    options: &DedupeOptions,
+    mut undo_log: W,
+) -> Result<DedupeOutcome>
+where
+    I: IntoIterator<Item = &'a DuplicateGroup>,
+    W: Write,
+{
+    let mut outcome = DedupeOutcome::default();
+    for group in groups {
+        let mut copies = vec![];
+        for path in &group.paths {
+            match fs::symlink_metadata(path) {
+                Ok(meta) if meta.is_file() => copies.push((path, meta)),
+                Ok(_) => outcome
+                    .skipped
+                    .push((path.clone(), "not a regular file".into())),
+                Err(e) => outcome.skipped.push((path.clone(), e.to_string())),
+            }
+        }
+        if copies.len() < 2 {
+            continue;
+        }
+        let keep = keep_index(&copies, options.keep, &options.prefer);
+        let (kept, kept_meta) = copies.remove(keep);
+
+        for (path, meta) in copies {
+            if same_inode(&kept_meta, &meta) {
+                outcome
+                    .skipped
+                    .push((path.clone(), "already linked".into()));
+                continue;
+            }
+            match same_content(kept, path) {
+                Ok(true) => {}
+                Ok(false) => {
+                    outcome
+                        .skipped
+                        .push((path.clone(), "content differs".into()));
+                    continue;
+                }
+                Err(e) => {
+                    outcome.skipped.push((path.clone(), e.to_string()));
+                    continue;
+                }
+            }
+            if !options.dry_run {
+                let logged = undo_entry(kept, path, &meta, options.method)
+                    .and_then(|entry| write_line(&mut undo_log, &entry));
+                if let Err(e) = logged {
+                    outcome.skipped.push((path.clone(), e.to_string()));
+                    continue;
+                }
+                if let Err(e) = replace(kept, path, &meta, options.method) {
+                    outcome.skipped.push((path.clone(), e.to_string()));
+                    // Restoring a file that was not replaced copies identical content,
+                    // so a marker that can not be written does no harm
+                    if let Ok(replaced) = fs::canonicalize(path) {
+                        if let Ok(replaced) = path_to_json(&replaced) {
+                            let _ = write_line(
+                                &mut undo_log,
+                                &json!({ "not_applied": true, "replaced": replaced }),
+                            );
+                        }
+                    }
+                    continue;
+                }
+            }
+            outcome.actions.push(DedupeAction {
+                kept: kept.clone(),
+                replaced: path.clone(),
+                size: meta.len(),
+            });
+        }
+    }
+    Ok(outcome)
+}
+
+/// Restore independent copies of every file replaced according to an undo log,
+/// most recent first, with their former permissions, modification time and, where
+/// allowed, owner. Return the number of restored files.
+pub fn undo<R: Read>(undo_log: R) -> Result<usize> {
+    let mut entries = vec![];
+    for line in BufReader::new(undo_log).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            entries.push(serde_json::from_str::<Value>(&line)?);
+        }
+    }
+    let mut restored = 0;
+    // Markers follow the entry they refer to, so they are seen first
+    let mut not_applied: Vec<&Value> = vec![];
+    for entry in entries.iter().rev() {
+        if entry["not_applied"] == true {
+            not_applied.push(&entry["replaced"]);
+            continue;
+        }
+        if let Some(i) = not_applied.iter().position(|r| **r == entry["replaced"]) {
+            not_applied.remove(i);
+            continue;
+        }
+        let (kept, replaced) = match (
+            path_from_json(&entry["kept"]),
+            path_from_json(&entry["replaced"]),
+        ) {
+            (Some(kept), Some(replaced)) => (kept, replaced),
+            _ => bail!("Invalid undo log entry: {}", entry),
+        };
+        let tmp = temp_path(&replaced);
+        let copied = restore_metadata(kept.as_ref(), &tmp, entry)
+            .and_then(|_| fs::rename(&tmp, &replaced).map_err(Into::into));
+        if let Err(e) = copied {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+        restored += 1;
+    }
+    Ok(restored)
+}
+
+/// Append a line to the undo log and flush it, so it is on disk before the file is replaced
+fn write_line<W: Write>(undo_log: &mut W, entry: &Value) -> Result<()> {
+    writeln!(undo_log, "{}", entry)?;
+    undo_log.flush()?;
+    Ok(())
+}
+
+/// Describe a replacement for the undo log, with the metadata of the replaced
+/// file that is lost when it becomes a link
+fn undo_entry(
+    kept: &Path,
+    replaced: &Path,
+    meta: &fs::Metadata,
+    method: LinkMethod,
+) -> Result<Value> {
+    let modified = meta.modified()?.duration_since(UNIX_EPOCH)?;
+    let (uid, gid) = owner(meta);
+    Ok(json!({
+        "method": method.name(),
+        "kept": path_to_json(&fs::canonicalize(kept)?)?,
+        "replaced": path_to_json(&fs::canonicalize(replaced)?)?,
+        "mode": mode(meta),
+        "modified": [modified.as_secs(), modified.subsec_nanos()],
+        "uid": uid,
+        "gid": gid,
+    }))
+}
+
+/// Copy `kept` to `tmp` and give it the metadata recorded in an undo log entry
+fn restore_metadata(kept: &Path, tmp: &Path, entry: &Value) -> Result<()> {
+    fs::copy(kept, tmp)?;
+    if let (Some(uid), Some(gid)) = (entry["uid"].as_u64(), entry["gid"].as_u64()) {
+        // Only root may give files away, so the copy stays ours otherwise
+        let _ = set_owner(tmp, uid as u32, gid as u32);
+    }
+    if let Some(mode) = entry["mode"].as_u64() {
+        set_mode(tmp, mode as u32)?;
+    }
+    if let (Some(secs), Some(nanos)) =
+        (entry["modified"][0].as_u64(), entry["modified"][1].as_u64())
+    {
+        set_modified(tmp, UNIX_EPOCH + Duration::new(secs, nanos as u32))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn path_to_json(path: &Path) -> Result<Value> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(match path.to_str() {
+        Some(path) => json!(path),
+        None => json!(path.as_os_str().as_bytes()),
+    })
+}
+
+#[cfg(not(unix))]
+fn path_to_json(path: &Path) -> Result<Value> {
+    match path.to_str() {
+        Some(path) => Ok(json!(path)),
+        None => bail!("{} is not valid Unicode", path.display()),
+    }
+}
+
+fn path_from_json(value: &Value) -> Option<PathBuf> {
+    match value {
+        Value::String(path) => Some(PathBuf::from(path)),
+        #[cfg(unix)]
+        Value::Array(bytes) => {
+            use std::os::unix::ffi::OsStringExt;
+            let bytes = bytes
+                .iter()
+                .map(|b| {
+                    b.as_u64()
+                        .and_then(|b| std::convert::TryFrom::try_from(b).ok())
+                })
+                .collect::<Option<Vec<u8>>>()?;
+            Some(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+        }
+        _ => None,
+    }
+}
+
+fn keep_index(
+    copies: &[(&PathBuf, fs::Metadata)],
+    policy: KeepPolicy,
+    prefer: &[PathBuf],
+) -> usize {
+    let modified = |i: &usize| copies[*i].1.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    let shortest = |i: &usize| {
+        let path = copies[*i].0;
+        (
+            path.components().count(),
+            path.as_os_str().len(),
+            path.clone(),
+        )
+    };
+    let choose = |candidates: Vec<usize>| match policy {
+        KeepPolicy::Oldest => candidates.into_iter().min_by_key(modified),
+        KeepPolicy::Newest => candidates.into_iter().max_by_key(modified),
+        KeepPolicy::ShortestPath => candidates.into_iter().min_by_key(shortest),
+    };
+    prefer
+        .iter()
+        .find_map(|dir| {
+            choose(
+                (0..copies.len())
+                    .filter(|i| copies[*i].0.starts_with(dir))
+                    .collect(),
+            )
+        })
+        .or_else(|| choose((0..copies.len()).collect()))
+        .unwrap_or_default()
+}
+
+/// Compare two files byte by byte
+fn same_content(a: &Path, b: &Path) -> Result<bool> {
+    let mut a = fs::File::open(a)?;
+    let mut b = fs::File::open(b)?;
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+    let mut buf_a = vec![0; 1 << 16];
+    let mut buf_b = vec![0; 1 << 16];
+    loop {
+        let len = a.read(&mut buf_a)?;
+        if len == 0 {
+            return Ok(b.read(&mut buf_b)? == 0);
+        }
+        match b.read_exact(&mut buf_b[..len]) {
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+            result => result?,
+        }
+        if buf_a[..len] != buf_b[..len] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Replace `path` by a link to `kept`. The link is created next to `path`
+/// and renamed over it, so `path` is never missing.
+fn replace(kept: &Path, path: &Path, meta: &fs::Metadata, method: LinkMethod) -> Result<()> {
+    let tmp = temp_path(path);
+    let linked = match method {
+        LinkMethod::Hardlink => fs::hard_link(kept, &tmp).map_err(anyhow::Error::from),
+        LinkMethod::Reflink => reflink(kept, &tmp)
+            .and_then(|_| fs::set_permissions(&tmp, meta.permissions()).map_err(Into::into)),
+    };
+    if let Err(e) = linked.and_then(|_| fs::rename(&tmp, path).map_err(Into::into)) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    Ok(())
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.dedupe", std::process::id()));
+    path.with_file_name(name)
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(src: &Path, dst: &Path) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+    let src = fs::File::open(src)?;
+    let dst = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dst)?;
+    // SAFETY: both descriptors are open for the duration of the call
+    if unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_src: &Path, _dst: &Path) -> Result<()> {
+    bail!("Reflinks are not supported on this platform")
+}
+
+#[cfg(unix)]
+fn same_inode(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_inode(_a: &fs::Metadata, _b: &fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn mode(meta: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(meta.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn mode(_meta: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn owner(meta: &fs::Metadata) -> (Option<u32>, Option<u32>) {
+    use std::os::unix::fs::MetadataExt;
+    (Some(meta.uid()), Some(meta.gid()))
+}
+
+#[cfg(not(unix))]
+fn owner(_meta: &fs::Metadata) -> (Option<u32>, Option<u32>) {
+    (None, None)
+}
+
+#[cfg(unix)]
+fn set_owner(path: &Path, uid: u32, gid: u32) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: the path is a valid C string for the duration of the call
+    if unsafe { libc::chown(path.as_ptr(), uid, gid) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_owner(_path: &Path, _uid: u32, _gid: u32) -> Result<()> {
+    Ok(())
+}
+
+/// Set the modification time without opening the file, which may be read-only
+#[cfg(unix)]
+fn set_modified(path: &Path, time: SystemTime) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    let since_epoch = time.duration_since(UNIX_EPOCH)?;
+    let times = [
+        libc::timespec {
+            tv_sec: 0,
+            tv_nsec: libc::UTIME_OMIT,
+        },
+        libc::timespec {
+            tv_sec: since_epoch.as_secs() as libc::time_t,
+            tv_nsec: since_epoch.subsec_nanos() as _,
+        },
+    ];
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: the path and times are valid for the duration of the call
+    if unsafe { libc::utimensat(libc::AT_FDCWD, path.as_ptr(), times.as_ptr(), 0) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_modified(path: &Path, time: SystemTime) -> Result<()> {
+    fs::OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .set_modified(time)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
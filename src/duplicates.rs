@@ -0,0 +1,178 @@
+//! Duplicate files and directories grouped by content, with the space that removing the copies would free.
+
+use crate::{DirInfo, File};
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Files or directories with identical content
+pub struct DuplicateGroup {
+    /// Content hash shared by all copies. For files, this is the key in `DirInfo::duplicates`.
+    pub hash: u64,
+    /// Size of each copy, including subdirectories
+    pub size: u64,
+    /// Number of copies. Hard links to the same file are a single copy.
+    pub count: usize,
+    /// Space taken by all but one copy
+    pub wasted: u64,
+    /// Paths of all copies, sorted
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    /// Group files with the same content. All paths are listed, but hard links
+    /// to the same file take no extra space and count as one copy.
+    pub fn new(hash: u64, files: &[File]) -> DuplicateGroup {
+        let size = files.first().map(|f| f.size).unwrap_or_default();
+        let mut inodes = HashSet::new();
+        let copies = files
+            .iter()
+            .filter(|f| f.inode.map_or(true, |inode| inodes.insert(inode)))
+            .count();
+        let mut group =
+            DuplicateGroup::from_paths(hash, size, files.iter().map(|f| f.path.clone()).collect());
+        group.count = copies;
+        group.wasted = size * copies.saturating_sub(1) as u64;
+        group
+    }
+
+    fn from_paths(hash: u64, size: u64, mut paths: Vec<PathBuf>) -> DuplicateGroup {
+        paths.sort();
+        let count = paths.len();
+        DuplicateGroup {
+            hash,
+            size,
+            count,
+            wasted: size * count.saturating_sub(1) as u64,
+            paths,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// All duplicate groups, most wasted space first
+pub struct DuplicateReport {
+    pub groups: Vec<DuplicateGroup>,
+    /// Space freed by keeping a single copy of every group
+    pub reclaimable: u64,
+}
+
+impl DirInfo {
+    /// Return the duplicate groups, leaving out empty files
+    pub fn duplicate_report(&self) -> DuplicateReport {
+        self.duplicate_report_with(false)
+    }
+
+    /// Return the duplicate groups. Empty files are all identical, so
+    /// including them adds a group of every empty file that was scanned.
+    pub fn duplicate_report_with(&self, include_empty: bool) -> DuplicateReport {
+        let groups: Vec<DuplicateGroup> = self
+            .duplicates
+            .iter()
+            .map(|(hash, files)| DuplicateGroup::new(*hash, files))
+            .filter(|g| g.count > 1 && (include_empty || g.size > 0))
+            .collect();
+        DuplicateReport::new(groups)
+    }
+
+    /// Return a hash of the content of every directory, built from the names and
+    /// hashes of its files and subdirectories. Directories with identical hashes
+    /// hold the same tree of files. This relies on file hashes, so it is meaningless
+    /// for imports without them, such as ncdu dumps.
+    pub fn directory_hashes(&self) -> HashMap<PathBuf, u64> {
+        let mut hashes = HashMap::new();
+        for path in self.tree.keys() {
+            self.directory_hash(path, &mut hashes);
+        }
+        hashes
+    }
+
+    fn directory_hash(&self, path: &Path, hashes: &mut HashMap<PathBuf, u64>) -> u64 {
+        if let Some(hash) = hashes.get(path) {
+            return *hash;
+        }
+        let mut entries: Vec<(String, u8, u64)> = vec![];
+        if let Some(dir) = self.tree.get(path) {
+            for file in &dir.files {
+                entries.push((name(&file.path), 0, file.hash));
+            }
+            for subdir in &dir.directories {
+                entries.push((name(subdir), 1, self.directory_hash(subdir, hashes)));
+            }
+        }
+        entries.sort();
+        let mut s: twox_hash::Xxh3Hash64 = Default::default();
+        entries.hash(&mut s);
+        let hash = s.finish();
+        hashes.insert(path.to_path_buf(), hash);
+        hash
+    }
+
+    /// Return groups of directories with identical content, leaving out empty ones.
+    /// Directories inside directories that are duplicates themselves are covered by
+    /// that larger group and left out. If a group has covered directories, one of
+    /// them is listed as the copy to keep, so that only uncovered copies count as wasted.
+    pub fn duplicate_directories(&self) -> DuplicateReport {
+        let hashes = self.directory_hashes();
+        let mut by_hash: HashMap<u64, Vec<&Path>> = HashMap::new();
+        for (path, hash) in &hashes {
+            by_hash.entry(*hash).or_default().push(path);
+        }
+        // A directory inside a duplicated ancestor also has a duplicated parent
+        let covered = |path: &Path| {
+            path.parent()
+                .and_then(|p| hashes.get(p))
+                .and_then(|h| by_hash.get(h))
+                .map(|paths| paths.len() > 1)
+                .unwrap_or(false)
+        };
+
+        let groups = by_hash
+            .iter()
+            .filter(|(_, paths)| paths.len() > 1)
+            .filter_map(|(hash, paths)| {
+                let (mut covered, mut paths): (Vec<&Path>, Vec<&Path>) =
+                    paths.iter().partition(|p| covered(p));
+                if paths.is_empty() {
+                    return None;
+                }
+                covered.sort();
+                paths.extend(covered.first());
+                if paths.len() < 2 {
+                    return None;
+                }
+                let size = self.tree.get(paths[0])?.combined_size;
+                Some(DuplicateGroup::from_paths(
+                    *hash,
+                    size,
+                    paths.iter().map(|p| p.to_path_buf()).collect(),
+                ))
+            })
+            .filter(|g| g.size > 0)
+            .collect();
+        DuplicateReport::new(groups)
+    }
+}
+
+impl DuplicateReport {
+    /// Sort groups by wasted space and add it up
+    fn new(mut groups: Vec<DuplicateGroup>) -> DuplicateReport {
+        groups.sort_by(|a, b| {
+            b.wasted
+                .cmp(&a.wasted)
+                .then(b.size.cmp(&a.size))
+                .then(a.paths.cmp(&b.paths))
+        });
+        DuplicateReport {
+            reclaimable: groups.iter().map(|g| g.wasted).sum(),
+            groups,
+        }
+    }
+}
+
+fn name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
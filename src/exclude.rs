@@ -0,0 +1,277 @@
+//! Include and exclude rules for scans, using the glob syntax of `.gitignore` files.
+//!
+//! A pattern without a slash matches a name at any depth, such as `*.o` or `.git`.
+//! A pattern with a slash matches a path relative to the scan root, or for ignore files
+//! relative to their directory. A trailing slash only matches directories, `**` matches
+//! any number of directories and a leading `!` re-includes what an earlier pattern excluded.
+
+use crate::{ScanOptions, SkipReason};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Ignore files honoured by ripgrep, in increasing order of precedence
+pub const DEFAULT_IGNORE_FILES: &[&str] = &[".gitignore", ".ignore"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(char),
+    /// `?`
+    Any,
+    /// `*`, not crossing a `/`
+    Star,
+    /// `**` followed by `/`, matching zero or more directories
+    AnyDirs,
+    /// `**` anywhere else, matching everything
+    AnyPath,
+    /// `[a-z]`, or `[!a-z]` when negated
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A single glob
+pub struct Pattern {
+    /// The pattern as written
+    pub glob: String,
+    pub negated: bool,
+    /// Only matches directories
+    pub dir_only: bool,
+    /// Matches the whole relative path rather than the name
+    anchored: bool,
+    tokens: Vec<Token>,
+}
+
+impl Pattern {
+    pub fn new(glob: &str) -> Pattern {
+        let mut pattern = glob.trim_end();
+        let negated = pattern.starts_with('!');
+        if negated {
+            pattern = &pattern[1..];
+        }
+        let dir_only = pattern.len() > 1 && pattern.ends_with('/');
+        if dir_only {
+            pattern = &pattern[..pattern.len() - 1];
+        }
+        let anchored = pattern.contains('/');
+        Pattern {
+            glob: glob.to_string(),
+            negated,
+            dir_only,
+            anchored,
+            tokens: tokenize(pattern.trim_start_matches('/')),
+        }
+    }
+
+    /// Check a path relative to the directory the pattern belongs to
+    pub fn matches(&self, relative: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let text: Vec<char> = if self.anchored {
+            relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/")
+                .chars()
+                .collect()
+        } else {
+            match relative.file_name() {
+                Some(name) => name.to_string_lossy().chars().collect(),
+                None => return false,
+            }
+        };
+        match_tokens(&self.tokens, &text)
+    }
+}
+
+/// Return the last pattern matching, which decides as in `.gitignore` files
+fn last_match<'a>(patterns: &'a [Pattern], relative: &Path, is_dir: bool) -> Option<&'a Pattern> {
+    patterns.iter().rev().find(|p| p.matches(relative, is_dir))
+}
+
+/// Read the patterns of an ignore file, skipping blank lines and comments
+pub fn read_ignore_file(path: &Path) -> std::io::Result<Vec<Pattern>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+        .map(Pattern::new)
+        .collect())
+}
+
+/// The rules of a scan, tracking the ignore files of the directories being walked
+pub(crate) struct Filter {
+    root: PathBuf,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    ignore_files: Vec<String>,
+    /// Ignore files in the directories above the current entry with their patterns, outermost first
+    ignores: Vec<(PathBuf, Vec<Pattern>)>,
+}
+
+impl Filter {
+    pub(crate) fn new(root: &Path, options: &ScanOptions) -> Filter {
+        let patterns = |globs: &[String]| globs.iter().map(|g| Pattern::new(g)).collect();
+        Filter {
+            root: root.to_path_buf(),
+            include: patterns(&options.include),
+            exclude: patterns(&options.exclude),
+            ignore_files: options.ignore_files.clone(),
+            ignores: vec![],
+        }
+    }
+
+    /// Return why a path below the root is left out, if it is.
+    /// Paths must be given in the order of a depth-first walk.
+    pub(crate) fn skip(&mut self, path: &Path, is_dir: bool) -> Option<SkipReason> {
+        while let Some((file, _)) = self.ignores.last() {
+            match file.parent() {
+                Some(dir) if path.starts_with(dir) => break,
+                _ => {
+                    self.ignores.pop();
+                }
+            }
+        }
+
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        if let Some(pattern) = last_match(&self.exclude, relative, is_dir) {
+            if !pattern.negated {
+                return Some(SkipReason::Excluded(pattern.glob.clone()));
+            }
+        }
+        // Deeper ignore files take precedence
+        for (file, patterns) in self.ignores.iter().rev() {
+            let dir = file.parent().unwrap_or(&self.root);
+            let relative = path.strip_prefix(dir).unwrap_or(path);
+            if let Some(pattern) = last_match(patterns, relative, is_dir) {
+                if pattern.negated {
+                    return None;
+                }
+                return Some(SkipReason::Ignored(file.clone()));
+            }
+        }
+        None
+    }
+
+    /// Read the ignore files of a directory that is walked
+    pub(crate) fn enter(&mut self, dir: &Path) {
+        for name in &self.ignore_files {
+            let file = dir.join(name);
+            if let Ok(patterns) = read_ignore_file(&file) {
+                self.ignores.push((file, patterns));
+            }
+        }
+    }
+
+    /// Return true if a file is collected. A file inside a directory matching
+    /// an include pattern is included as well.
+    pub(crate) fn include(&self, path: &Path) -> bool {
+        if self.include.is_empty() {
+            return true;
+        }
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        relative.ancestors().any(|a| {
+            !a.as_os_str().is_empty()
+                && last_match(&self.include, a, a != relative)
+                    .map(|p| !p.negated)
+                    .unwrap_or(false)
+        })
+    }
+}
+
+fn tokenize(pattern: &str) -> Vec<Token> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                if chars.get(i + 2) == Some(&'/') {
+                    tokens.push(Token::AnyDirs);
+                    i += 3;
+                } else {
+                    tokens.push(Token::AnyPath);
+                    i += 2;
+                }
+                continue;
+            }
+            '*' => tokens.push(Token::Star),
+            '?' => tokens.push(Token::Any),
+            '[' => {
+                if let Some((token, len)) = class(&chars[i..]) {
+                    tokens.push(token);
+                    i += len;
+                    continue;
+                }
+                tokens.push(Token::Literal('['));
+            }
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                tokens.push(Token::Literal(chars[i]));
+            }
+            c => tokens.push(Token::Literal(c)),
+        }
+        i += 1;
+    }
+    tokens
+}
+
+/// Parse a character class starting at `[`, returning it and its length.
+/// An unterminated class is taken literally.
+fn class(chars: &[char]) -> Option<(Token, usize)> {
+    let mut i = 1;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = vec![];
+    // A `]` right at the start is part of the class
+    let start = i;
+    while i < chars.len() && (chars[i] != ']' || i == start) {
+        let low = chars[i];
+        if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|c| *c != ']') {
+            ranges.push((low, chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((low, low));
+            i += 1;
+        }
+    }
+    if i >= chars.len() {
+        return None;
+    }
+    Some((Token::Class { negated, ranges }, i + 1))
+}
+
+fn match_tokens(tokens: &[Token], text: &[char]) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return text.is_empty(),
+    };
+    match token {
+        Token::Literal(c) => text.first() == Some(c) && match_tokens(rest, &text[1..]),
+        Token::Any => text.first().is_some_and(|c| *c != '/') && match_tokens(rest, &text[1..]),
+        Token::Class { negated, ranges } => {
+            text.first().is_some_and(|c| {
+                *c != '/' && ranges.iter().any(|(l, h)| (l..=h).contains(&c)) != *negated
+            }) && match_tokens(rest, &text[1..])
+        }
+        Token::Star => {
+            for i in 0..=text.len() {
+                if match_tokens(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Token::AnyDirs => (0..=text.len())
+            .filter(|i| *i == 0 || text[i - 1] == '/')
+            .any(|i| match_tokens(rest, &text[i..])),
+        Token::AnyPath => (0..=text.len()).any(|i| match_tokens(rest, &text[i..])),
+    }
+}
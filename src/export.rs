@@ -0,0 +1,135 @@
+//! Flat exports of files and directories as CSV or JSON Lines, for spreadsheets and data frames.
+//!
+//! Rows are written one at a time straight from the `DirInfo`, nothing is collected first.
+
+use crate::{DirInfo, Directory, File};
+use anyhow::Result;
+use serde_json::json;
+use std::io::Write;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Comma separated values with a header row
+    Csv,
+    /// One JSON object per line
+    JsonLines,
+}
+
+const FILE_COLUMNS: [&str; 6] = ["path", "size", "allocated", "ext", "mtime", "hash"];
+const DIR_COLUMNS: [&str; 5] = ["path", "depth", "size", "combined_size", "files"];
+
+/// Write one row per file below `root`: path, size, allocated size, extension,
+/// modification time in seconds since the epoch and content hash in hex
+pub fn write_files<W: Write>(
+    info: &DirInfo,
+    root: &Path,
+    format: ExportFormat,
+    mut w: W,
+) -> Result<()> {
+    if format == ExportFormat::Csv {
+        writeln!(w, "{}", FILE_COLUMNS.join(","))?;
+    }
+    for file in info.files.iter().filter(|f| f.path.starts_with(root)) {
+        write_file(file, format, &mut w)?;
+    }
+    Ok(())
+}
+
+/// Write one row per directory below `root`, parents first: path, levels below `root`,
+/// size of its files, size including subdirectories and number of files
+pub fn write_dirs<W: Write>(
+    info: &DirInfo,
+    root: &Path,
+    format: ExportFormat,
+    mut w: W,
+) -> Result<()> {
+    if format == ExportFormat::Csv {
+        writeln!(w, "{}", DIR_COLUMNS.join(","))?;
+    }
+    if let Some(dir) = info.tree.get(root) {
+        write_dir(info, dir, 0, format, &mut w)?;
+    }
+    Ok(())
+}
+
+fn write_file<W: Write>(file: &File, format: ExportFormat, w: &mut W) -> Result<()> {
+    let path = file.path.display().to_string();
+    let mtime = file
+        .modified
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .ok();
+    let hash = format!("{:016x}", file.hash);
+    match format {
+        ExportFormat::Csv => writeln!(
+            w,
+            "{},{},{},{},{},{}",
+            csv_field(&path),
+            file.size,
+            file.allocated,
+            csv_field(file.ext.as_deref().unwrap_or_default()),
+            mtime.map(|t| t.to_string()).unwrap_or_default(),
+            hash
+        )?,
+        ExportFormat::JsonLines => writeln!(
+            w,
+            "{}",
+            json!({
+                "path": path,
+                "size": file.size,
+                "allocated": file.allocated,
+                "ext": file.ext,
+                "mtime": mtime,
+                "hash": hash,
+            })
+        )?,
+    }
+    Ok(())
+}
+
+fn write_dir<W: Write>(
+    info: &DirInfo,
+    dir: &Directory,
+    depth: usize,
+    format: ExportFormat,
+    w: &mut W,
+) -> Result<()> {
+    let path = dir.path.display().to_string();
+    match format {
+        ExportFormat::Csv => writeln!(
+            w,
+            "{},{},{},{},{}",
+            csv_field(&path),
+            depth,
+            dir.size,
+            dir.combined_size,
+            dir.files.len()
+        )?,
+        ExportFormat::JsonLines => writeln!(
+            w,
+            "{}",
+            json!({
+                "path": path,
+                "depth": depth,
+                "size": dir.size,
+                "combined_size": dir.combined_size,
+                "files": dir.files.len(),
+            })
+        )?,
+    }
+    for subdir in dir.directories.iter().filter_map(|d| info.tree.get(d)) {
+        write_dir(info, subdir, depth + 1, format, w)?;
+    }
+    Ok(())
+}
+
+/// Quote a field if it contains a separator, quote or line break
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
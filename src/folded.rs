@@ -0,0 +1,64 @@
+//! Folded stacks as read by flamegraph tools such as `flamegraph.pl` and inferno,
+//! one line per file: `root;dir;subdir;file.ext <bytes>`.
+
+use crate::{DirInfo, Directory};
+use anyhow::Result;
+use std::io::Write;
+use std::path::Path;
+
+/// Write the tree below `root` as folded stacks. Directories `max_depth` levels
+/// below `root` are collapsed into a single line carrying their combined size.
+pub fn write_folded<W: Write>(
+    info: &DirInfo,
+    root: &Path,
+    max_depth: Option<usize>,
+    mut w: W,
+) -> Result<()> {
+    if let Some(dir) = info.tree.get(root) {
+        write_dir(
+            info,
+            dir,
+            &frame(&root.display().to_string()),
+            0,
+            max_depth,
+            &mut w,
+        )?;
+    }
+    Ok(())
+}
+
+fn write_dir<W: Write>(
+    info: &DirInfo,
+    dir: &Directory,
+    stack: &str,
+    depth: usize,
+    max_depth: Option<usize>,
+    w: &mut W,
+) -> Result<()> {
+    if max_depth.map(|d| depth >= d).unwrap_or(false) {
+        // Flamegraph tools drop empty samples anyway
+        if dir.combined_size > 0 {
+            writeln!(w, "{} {}", stack, dir.combined_size)?;
+        }
+        return Ok(());
+    }
+    for file in dir.files.iter().filter(|f| f.size > 0) {
+        writeln!(w, "{};{} {}", stack, frame(&name(&file.path)), file.size)?;
+    }
+    for subdir in dir.directories.iter().filter_map(|d| info.tree.get(d)) {
+        let stack = format!("{};{}", stack, frame(&name(&subdir.path)));
+        write_dir(info, subdir, &stack, depth + 1, max_depth, w)?;
+    }
+    Ok(())
+}
+
+fn name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// Replace characters that have a meaning in the folded format
+fn frame(name: &str) -> String {
+    name.replace([';', '\n', '\r'], "_")
+}
@@ -0,0 +1,237 @@
+//! A self-contained HTML report, with no scripts or external resources.
+//! The tree is made collapsible with `<details>` elements.
+
+use crate::DirInfo;
+use anyhow::Result;
+use bytesize::ByteSize;
+use std::io::Write;
+use std::path::Path;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+td, th { padding: 0.2em 0.8em; text-align: left; }
+td.size { text-align: right; white-space: nowrap; font-family: monospace; }
+tr:nth-child(even) { background: #f3f3f3; }
+.bar { background: #4a90d9; height: 0.8em; min-width: 1px; }
+details { margin-left: 1.2em; }
+summary { cursor: pointer; font-family: monospace; }
+.file { margin-left: 2.4em; font-family: monospace; color: #555; }
+";
+
+#[derive(Debug, Clone)]
+/// What to include in a report
+pub struct HtmlOptions {
+    /// Number of entries in the directory, type and duplicate tables
+    pub top: usize,
+    /// Directory levels shown in the tree
+    pub max_depth: usize,
+    /// Entries shown per directory in the tree, the rest is summarized
+    pub max_children: usize,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        HtmlOptions {
+            top: 25,
+            max_depth: 8,
+            max_children: 50,
+        }
+    }
+}
+
+/// Write a report of the scan below `root` as a single HTML page
+pub fn write_html_report<W: Write>(
+    info: &DirInfo,
+    root: &Path,
+    options: &HtmlOptions,
+    mut w: W,
+) -> Result<()> {
+    let title = format!("Disk usage of {}", root.display());
+    writeln!(w, "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">")?;
+    writeln!(
+        w,
+        "<title>{}</title><style>{}</style></head><body>",
+        escape(&title),
+        STYLE
+    )?;
+    writeln!(w, "<h1>{}</h1>", escape(&title))?;
+
+    writeln!(w, "<table>")?;
+    let totals = [
+        ("Size", ByteSize(info.combined_size).to_string()),
+        (
+            "Allocated",
+            ByteSize(info.combined_allocated_size).to_string(),
+        ),
+        ("Files", info.files.len().to_string()),
+        (
+            "Directories",
+            info.tree
+                .keys()
+                .filter(|p| p.starts_with(root))
+                .count()
+                .to_string(),
+        ),
+    ];
+    for (label, value) in &totals {
+        writeln!(w, "<tr><th>{}</th><td>{}</td></tr>", label, value)?;
+    }
+    writeln!(w, "</table>")?;
+
+    writeln!(w, "<h2>Largest directories</h2><table>")?;
+    writeln!(
+        w,
+        "<tr><th>Size</th><th>Including subdirectories</th><th>Path</th></tr>"
+    )?;
+    for dir in info.dirs_by_size.iter().take(options.top) {
+        writeln!(
+            w,
+            "<tr><td class=\"size\">{}</td><td class=\"size\">{}</td><td>{}</td></tr>",
+            ByteSize(dir.size),
+            ByteSize(dir.combined_size),
+            escape(&dir.path.display().to_string())
+        )?;
+    }
+    writeln!(w, "</table>")?;
+
+    writeln!(w, "<h2>Largest file types</h2><table>")?;
+    writeln!(
+        w,
+        "<tr><th>Size</th><th>Files</th><th>Type</th><th></th></tr>"
+    )?;
+    let largest_type = info
+        .types_by_size
+        .first()
+        .map(|t| t.size)
+        .unwrap_or_default();
+    for ftype in info.types_by_size.iter().take(options.top) {
+        writeln!(
+            w,
+            "<tr><td class=\"size\">{}</td><td class=\"size\">{}</td><td>{}</td><td style=\"width: 20em\">{}</td></tr>",
+            ByteSize(ftype.size),
+            ftype.files.len(),
+            escape(&ftype.ext),
+            bar(ftype.size, largest_type)
+        )?;
+    }
+    writeln!(w, "</table>")?;
+
+    let duplicates = info.duplicate_report();
+    writeln!(
+        w,
+        "<h2>Duplicates</h2><p>{} reclaimable in {} groups</p><table>",
+        ByteSize(duplicates.reclaimable),
+        duplicates.groups.len()
+    )?;
+    writeln!(w, "<tr><th>Reclaimable</th><th>Copies</th></tr>")?;
+    for group in duplicates.groups.iter().take(options.top) {
+        let paths: Vec<String> = group
+            .paths
+            .iter()
+            .map(|p| escape(&p.display().to_string()))
+            .collect();
+        writeln!(
+            w,
+            "<tr><td class=\"size\">{}</td><td>{}</td></tr>",
+            ByteSize(group.wasted),
+            paths.join("<br>")
+        )?;
+    }
+    writeln!(w, "</table>")?;
+
+    writeln!(w, "<h2>Tree</h2>")?;
+    write_tree(info, root, 0, options, &mut w)?;
+
+    writeln!(w, "</body></html>")?;
+    Ok(())
+}
+
+fn write_tree<W: Write>(
+    info: &DirInfo,
+    path: &Path,
+    depth: usize,
+    options: &HtmlOptions,
+    w: &mut W,
+) -> Result<()> {
+    let dir = match info.tree.get(path) {
+        Some(dir) => dir,
+        None => return Ok(()),
+    };
+    let name = if depth == 0 {
+        path.display().to_string()
+    } else {
+        file_name(path)
+    };
+    // Only the root is expanded initially
+    writeln!(
+        w,
+        "<details{}><summary>{} {}</summary>",
+        if depth == 0 { " open" } else { "" },
+        ByteSize(dir.combined_size)
+            .to_string()
+            .replace(' ', "&nbsp;"),
+        escape(&name)
+    )?;
+
+    if depth < options.max_depth {
+        let mut subdirs: Vec<_> = dir
+            .directories
+            .iter()
+            .filter_map(|d| info.tree.get(d))
+            .collect();
+        subdirs.sort_by_key(|d| std::cmp::Reverse(d.combined_size));
+        for subdir in subdirs.iter().take(options.max_children) {
+            write_tree(info, &subdir.path, depth + 1, options, w)?;
+        }
+
+        let files = dir.sorted_files();
+        for file in files.iter().take(options.max_children) {
+            writeln!(
+                w,
+                "<div class=\"file\">{} {}</div>",
+                ByteSize(file.size).to_string().replace(' ', "&nbsp;"),
+                escape(&file_name(&file.path))
+            )?;
+        }
+
+        let hidden = subdirs.len().saturating_sub(options.max_children)
+            + files.len().saturating_sub(options.max_children);
+        if hidden > 0 {
+            writeln!(w, "<div class=\"file\">{} more entries</div>", hidden)?;
+        }
+    }
+    writeln!(w, "</details>")?;
+    Ok(())
+}
+
+fn bar(size: u64, max: u64) -> String {
+    let percent = if max > 0 {
+        size as f64 / max as f64 * 100.0
+    } else {
+        0.0
+    };
+    format!("<div class=\"bar\" style=\"width: {:.1}%\"></div>", percent)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// Escape text for use in HTML content and attributes
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
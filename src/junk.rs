@@ -0,0 +1,340 @@
+//! Detection of regenerable data such as caches and build outputs.
+//!
+//! A [`JunkDetector`] runs a list of [`JunkRule`]s over every directory of a scan.
+//! Directories below a match are not checked again, so a `node_modules` inside
+//! another one is reported as part of the outer one.
+
+use crate::{DirInfo, Directory};
+use std::env;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// How sure a rule is that a match can be deleted without losing anything
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl std::fmt::Display for Confidence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Confidence::Low => write!(f, "low"),
+            Confidence::Medium => write!(f, "medium"),
+            Confidence::High => write!(f, "high"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A directory or file found by a rule
+pub struct JunkMatch {
+    pub path: PathBuf,
+    /// Name of the rule that matched
+    pub rule: String,
+    /// Size, including subdirectories
+    pub size: u64,
+    pub confidence: Confidence,
+    /// Command that recreates the data, if there is one
+    pub regenerate: Option<String>,
+}
+
+/// A rule recognizing junk
+pub trait JunkRule {
+    /// Return matches for `dir` itself or for files directly in it
+    fn check(&self, info: &DirInfo, dir: &Directory) -> Vec<JunkMatch>;
+}
+
+#[derive(Debug, Clone)]
+/// Matches directories by name, such as `node_modules`
+pub struct DirectoryRule {
+    pub name: String,
+    pub dir_name: String,
+    /// Files next to the directory that confirm a match, such as `Cargo.toml` next to `target`
+    pub siblings: Vec<String>,
+    /// Files in the directory that confirm a match, such as `CACHEDIR.TAG`
+    pub contents: Vec<String>,
+    pub confirmed: Confidence,
+    /// Confidence without confirmation, or `None` to only report confirmed matches
+    pub unconfirmed: Option<Confidence>,
+    pub regenerate: Option<String>,
+}
+
+impl DirectoryRule {
+    pub fn new(name: &str, dir_name: &str, confirmed: Confidence) -> DirectoryRule {
+        DirectoryRule {
+            name: name.to_string(),
+            dir_name: dir_name.to_string(),
+            siblings: vec![],
+            contents: vec![],
+            confirmed,
+            unconfirmed: None,
+            regenerate: None,
+        }
+    }
+
+    pub fn with_siblings(mut self, siblings: &[&str]) -> Self {
+        self.siblings = siblings.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    pub fn with_contents(mut self, contents: &[&str]) -> Self {
+        self.contents = contents.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    pub fn with_unconfirmed(mut self, confidence: Confidence) -> Self {
+        self.unconfirmed = Some(confidence);
+        self
+    }
+
+    pub fn with_regenerate(mut self, command: &str) -> Self {
+        self.regenerate = Some(command.to_string());
+        self
+    }
+}
+
+impl JunkRule for DirectoryRule {
+    fn check(&self, info: &DirInfo, dir: &Directory) -> Vec<JunkMatch> {
+        if dir
+            .path
+            .file_name()
+            .map(|n| n != self.dir_name.as_str())
+            .unwrap_or(true)
+        {
+            return vec![];
+        }
+        let has_file = |dir: Option<&Directory>, names: &[String]| {
+            dir.map(|d| {
+                d.files
+                    .iter()
+                    .any(|f| names.iter().any(|n| f.path.file_name() == Some(n.as_ref())))
+            })
+            .unwrap_or(false)
+        };
+        let parent = dir.path.parent().and_then(|p| info.tree.get(p));
+        // Rules without confirming files match on the name alone
+        let confirmed = (self.siblings.is_empty() && self.contents.is_empty())
+            || has_file(parent, &self.siblings)
+            || has_file(Some(dir), &self.contents);
+        let confidence = if confirmed {
+            Some(self.confirmed)
+        } else {
+            self.unconfirmed
+        };
+        confidence
+            .map(|confidence| {
+                vec![JunkMatch {
+                    path: dir.path.clone(),
+                    rule: self.name.clone(),
+                    size: dir.combined_size,
+                    confidence,
+                    regenerate: self.regenerate.clone(),
+                }]
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Matches a directory at a fixed location, such as `~/.cache`
+pub struct PathRule {
+    pub name: String,
+    /// Absolute path. Relative scan paths are resolved against the current directory.
+    pub path: PathBuf,
+    pub confidence: Confidence,
+    pub regenerate: Option<String>,
+}
+
+impl JunkRule for PathRule {
+    fn check(&self, _info: &DirInfo, dir: &Directory) -> Vec<JunkMatch> {
+        // Cheap check first, as resolving the path needs the current directory
+        if dir.path.file_name().is_none() || dir.path.file_name() != self.path.file_name() {
+            return vec![];
+        }
+        if absolute(&dir.path) != self.path {
+            return vec![];
+        }
+        vec![JunkMatch {
+            path: dir.path.clone(),
+            rule: self.name.clone(),
+            size: dir.combined_size,
+            confidence: self.confidence,
+            regenerate: self.regenerate.clone(),
+        }]
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Matches swap, backup and autosave files of vim and emacs
+pub struct EditorFileRule;
+
+impl JunkRule for EditorFileRule {
+    fn check(&self, _info: &DirInfo, dir: &Directory) -> Vec<JunkMatch> {
+        dir.files
+            .iter()
+            .filter(|f| {
+                let name = f
+                    .path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                is_editor_file(&name)
+            })
+            .map(|f| JunkMatch {
+                path: f.path.clone(),
+                rule: "Editor swap file".to_string(),
+                size: f.size,
+                // The editor may still be running and need it for recovery
+                confidence: Confidence::Low,
+                regenerate: None,
+            })
+            .collect()
+    }
+}
+
+fn is_editor_file(name: &str) -> bool {
+    let vim_swap = name.starts_with('.')
+        && name.len() > 5
+        && [".swp", ".swo", ".swn"].iter().any(|x| name.ends_with(x));
+    let emacs_autosave = name.len() > 2 && name.starts_with('#') && name.ends_with('#');
+    let backup = name.len() > 1 && name.ends_with('~');
+    vim_swap || emacs_autosave || backup
+}
+
+/// Runs rules over a scan
+pub struct JunkDetector {
+    rules: Vec<Box<dyn JunkRule>>,
+}
+
+impl Default for JunkDetector {
+    /// A detector with the built-in rules
+    fn default() -> Self {
+        use Confidence::*;
+        let mut detector = JunkDetector::empty();
+        detector.add(
+            DirectoryRule::new("Rust build output", "target", High)
+                .with_siblings(&["Cargo.toml"])
+                .with_contents(&["CACHEDIR.TAG"])
+                .with_regenerate("cargo build"),
+        );
+        detector.add(
+            DirectoryRule::new("Node.js packages", "node_modules", High)
+                .with_siblings(&["package.json"])
+                .with_unconfirmed(Medium)
+                .with_regenerate("npm install"),
+        );
+        detector.add(
+            DirectoryRule::new("Python bytecode", "__pycache__", High)
+                .with_regenerate("python -m compileall ."),
+        );
+        detector.add(
+            DirectoryRule::new("Gradle cache", ".gradle", High)
+                .with_siblings(&[
+                    "build.gradle",
+                    "build.gradle.kts",
+                    "settings.gradle",
+                    "settings.gradle.kts",
+                ])
+                .with_unconfirmed(Medium)
+                .with_regenerate("gradle build"),
+        );
+        detector.add(
+            DirectoryRule::new("Python virtual environment", ".venv", Medium)
+                .with_siblings(&["requirements.txt", "pyproject.toml", "Pipfile"])
+                .with_unconfirmed(Low)
+                .with_regenerate(
+                    "python -m venv .venv && .venv/bin/pip install -r requirements.txt",
+                ),
+        );
+        if let Some(home) = env::var_os("HOME") {
+            let home = PathBuf::from(home);
+            detector.add(PathRule {
+                name: "User cache".to_string(),
+                path: home.join(".cache"),
+                confidence: Medium,
+                regenerate: None,
+            });
+            detector.add(PathRule {
+                name: "Rootless container layers".to_string(),
+                path: home.join(".local/share/containers/storage/overlay"),
+                confidence: Low,
+                regenerate: Some("podman system prune".to_string()),
+            });
+        }
+        detector.add(PathRule {
+            name: "Docker layers".to_string(),
+            path: PathBuf::from("/var/lib/docker/overlay2"),
+            confidence: Low,
+            regenerate: Some("docker system prune".to_string()),
+        });
+        detector.add(EditorFileRule);
+        detector
+    }
+}
+
+impl JunkDetector {
+    /// A detector without any rules
+    pub fn empty() -> JunkDetector {
+        JunkDetector { rules: vec![] }
+    }
+
+    /// Add a rule. Rules added earlier win if several match the same directory.
+    pub fn add<R: JunkRule + 'static>(&mut self, rule: R) {
+        self.rules.push(Box::new(rule));
+    }
+
+    /// Return all matches, largest first
+    pub fn detect(&self, info: &DirInfo) -> Vec<JunkMatch> {
+        let mut dirs: Vec<&Directory> = info.tree.values().collect();
+        // Sorted paths put every directory right before its subdirectories
+        dirs.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut matches: Vec<JunkMatch> = vec![];
+        let mut matched_dir: Option<&Path> = None;
+        for dir in dirs {
+            if matched_dir
+                .map(|m| dir.path.starts_with(m))
+                .unwrap_or(false)
+            {
+                continue;
+            }
+            let mut dir_match = None;
+            let mut file_matches = vec![];
+            for found in self.rules.iter().flat_map(|rule| rule.check(info, dir)) {
+                if found.path != dir.path {
+                    file_matches.push(found);
+                } else if dir_match.is_none() {
+                    dir_match = Some(found);
+                }
+            }
+            // Files are already included in a match of their directory
+            match dir_match {
+                Some(m) => {
+                    matched_dir = Some(&dir.path);
+                    matches.push(m);
+                }
+                None => matches.extend(file_matches),
+            }
+        }
+        matches.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        matches
+    }
+}
+
+impl DirInfo {
+    /// Return regenerable data found by the built-in rules, largest first
+    pub fn junk(&self) -> Vec<JunkMatch> {
+        JunkDetector::default().detect(self)
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    let path = path.strip_prefix(".").unwrap_or(path);
+    env::current_dir()
+        .map(|cwd| cwd.join(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
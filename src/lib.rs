@@ -1,26 +1,129 @@
 #[cfg(test)]
 mod tests;
+pub mod age;
+pub mod cachedir;
+pub mod category;
+pub mod dedupe;
+pub mod duplicates;
+pub mod exclude;
+pub mod export;
+pub mod folded;
+pub mod html;
+pub mod junk;
+pub mod magic;
+pub mod mounts;
+pub mod ncdu;
+pub mod overlap;
+pub mod owners;
+pub mod prometheus;
+pub mod sunburst;
+pub mod svg;
+pub mod trash;
+pub mod treemap;
 
 use bytesize::ByteSize;
 use log::{info, error, debug};
 use walkdir::WalkDir;
 use rayon::prelude::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use std::io::{Read, BufReader};
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 use std::fs;
-use anyhow::Result;
+use anyhow::{Context, Result};
 
 #[derive(Debug, Clone)]
 /// A File, representing a file on disk
 pub struct File {
     pub size: u64,
+    /// Space allocated on disk, which differs from `size` for sparse or compressed files
+    pub allocated: u64,
+    /// Extension of the file name, in lower case
     pub ext: Option<String>,
     pub path: PathBuf,
     pub modified: SystemTime,
-    pub hash: u64
+    /// Last access, if the platform records it
+    pub accessed: Option<SystemTime>,
+    /// Creation time, if the platform and filesystem record it
+    pub created: Option<SystemTime>,
+    /// Owning user, on unix
+    pub uid: Option<u32>,
+    /// Owning group, on unix
+    pub gid: Option<u32>,
+    /// Permission bits and file type, on unix
+    pub mode: Option<u32>,
+    /// MIME type detected from the content, if sniffing was enabled
+    pub mime: Option<String>,
+    /// Extension usually used for the type detected from the content, such as `png`,
+    /// if the file has no extension or one of a different kind than its content
+    pub detected_ext: Option<String>,
+    /// Hash of the content, 0 if it is not known, such as for unreadable or imported files
+    pub hash: u64,
+    /// Device and inode number, on unix. Hard links share them.
+    pub inode: Option<(u64, u64)>,
+}
+
+impl File {
+    /// Read a file from disk, as a scan would without sniffing types
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<File> {
+        let meta = fs::metadata(&path)?;
+        Ok(File::read(path.as_ref(), &meta, false))
+    }
+
+    /// The type the file is counted as: the type detected from its content,
+    /// if it was sniffed and contradicts the extension, or else its extension
+    pub fn type_ext(&self) -> Option<&str> {
+        self.detected_ext.as_deref().or(self.ext.as_deref())
+    }
+
+    fn read(path: &Path, meta: &fs::Metadata, sniff: bool) -> File {
+        let ext_string: Option<String> = path
+            .extension()
+            .map(|x| x.to_string_lossy().to_string().to_lowercase());
+        let (hash, magic) = hash_file(path, sniff).unwrap_or_default();
+        debug!("{:?} is {}", path, hash);
+        let (uid, gid, mode) = ownership(meta);
+        let detected_ext = magic
+            .filter(|m| overrides_ext(ext_string.as_deref(), m))
+            .map(|m| m.ext.to_string());
+        File {
+            size: meta.len(),
+            allocated: allocated_size(meta),
+            ext: ext_string,
+            path: path.to_path_buf(),
+            modified: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            accessed: meta.accessed().ok(),
+            created: meta.created().ok(),
+            uid,
+            gid,
+            mode,
+            mime: magic.map(|m| m.mime.to_string()),
+            detected_ext,
+            hash,
+            inode: inode(meta),
+        }
+    }
+}
+
+/// Whether a detected type replaces an extension: only if there is none, or if it is
+/// known to be of a different kind, like a PNG image named `.txt`. Container formats
+/// such as zip never do, as `.docx` or `.jar` files are zip files as well.
+fn overrides_ext(ext: Option<&str>, magic: &magic::Magic) -> bool {
+    let ext = match ext {
+        Some(ext) => ext,
+        None => return true,
+    };
+    if magic::CONTAINER_EXTENSIONS.contains(&magic.ext) {
+        return false;
+    }
+    match (
+        category::builtin_category(ext),
+        category::builtin_category(magic.ext),
+    ) {
+        (Some(named), Some(detected)) => named != detected,
+        _ => false,
+    }
 }
 
 impl std::fmt::Display for File {
@@ -33,11 +136,15 @@ impl std::fmt::Display for File {
             Size: {}
             Ext: {:?}
             Modified: {:?}
+            Accessed: {:?}
+            Created: {:?}
         ",
             self.path.display(),
             self.size,
             self.ext,
-            self.modified.duration_since(std::time::UNIX_EPOCH)
+            self.modified.duration_since(std::time::UNIX_EPOCH),
+            self.accessed.map(|t| t.duration_since(std::time::UNIX_EPOCH)),
+            self.created.map(|t| t.duration_since(std::time::UNIX_EPOCH))
         )
     }
 }
@@ -47,10 +154,18 @@ impl std::fmt::Display for File {
 pub struct Directory {
     pub size: u64,
     pub combined_size: u64,
+    /// Allocated size of the files in this directory
+    pub allocated_size: u64,
+    /// Allocated size of this directory and all subdirectories
+    pub combined_allocated_size: u64,
     pub path: PathBuf,
     pub files: Vec<File>,
     pub directories: Vec<PathBuf>,
     pub parent: Option<PathBuf>,
+    /// Tagged as cache with a `CACHEDIR.TAG`, if the scan was asked to label caches
+    pub cache: bool,
+    /// Filesystem type, if this directory is a mount point found by the scan
+    pub mount: Option<String>,
 }
 
 impl Directory {
@@ -66,9 +181,13 @@ impl Directory {
             files: self.files.clone(),
             size: self.size,
             combined_size: self.size,
+            allocated_size: self.allocated_size,
+            combined_allocated_size: self.allocated_size,
             path: PathBuf::from("Files"),
             directories: vec![],
             parent: self.parent.clone(),
+            cache: self.cache,
+            mount: None,
         }
     }
 
@@ -77,18 +196,17 @@ impl Directory {
         let mut sorted_dirs: Vec<Directory> = self
             .directories
             .iter()
-            .map(|d| info.tree.get(d))
-            .flatten()
+            .filter_map(|d| info.tree.get(d))
             .cloned()
             .collect();
-        sorted_dirs.sort_by(|a, b| b.combined_size.cmp(&a.combined_size));
+        sorted_dirs.sort_by_key(|d| std::cmp::Reverse(d.combined_size));
         sorted_dirs
     }
 
     /// Return a list of files by size
     pub fn sorted_files(&self) -> Vec<File> {
         let mut sorted_files = self.files.clone();
-        sorted_files.sort_by(|a, b| b.size.cmp(&a.size));
+        sorted_files.sort_by_key(|f| std::cmp::Reverse(f.size));
         sorted_files
     }
 }
@@ -145,8 +263,18 @@ pub struct DirInfo {
     pub tree: HashMap<PathBuf, Directory>,
     /// Cumulated size
     pub combined_size: u64,
+    /// Cumulated allocated size
+    pub combined_allocated_size: u64,
     /// All duplicates
     pub duplicates: HashMap<u64, Vec<File>>,
+    /// How access times are recorded on the scanned filesystem.
+    /// Unless this is reliable, `File::accessed` should not be trusted.
+    pub atime_mode: mounts::AtimeMode,
+    /// The scanned path. Directory sizes are added up to and including it.
+    /// Empty for an archive, whose entries are relative to it.
+    pub root: PathBuf,
+    /// Paths left out of the scan, with the reason
+    pub skipped: Vec<(PathBuf, SkipReason)>,
 }
 
 impl DirInfo {
@@ -185,10 +313,19 @@ impl DirInfo {
         dirs
     }
 
+    /// Return all files below `root`, including files in subdirectories
+    pub fn subtree_files<'a>(&'a self, root: &'a Directory) -> impl Iterator<Item = &'a File> {
+        self.tree
+            .values()
+            .filter(move |d| d.path.starts_with(&root.path))
+            .flat_map(|d| d.files.iter())
+    }
+
     /// Return all duplicates
     pub fn duplicates_from_files(&self) -> HashMap<u64, Vec<File>> {
         let mut dupemap: HashMap<u64, Vec<File>> = HashMap::new();
-        for file in &self.files {
+        // Files of unknown content are not copies of each other
+        for file in self.files.iter().filter(|f| f.hash != 0) {
             dupemap
                 .entry(file.hash)
                 .and_modify(|e| e.push(file.clone()))
@@ -208,141 +345,418 @@ impl DirInfo {
             .collect()
     }
 
+    /// Add a directory to the list of subdirectories of its parent, unless it is already
+    /// the last one listed. A directory is only listed again right after it was inserted,
+    /// when its first file is, so comparing the last one is enough and keeps wide
+    /// directories fast.
+    pub(crate) fn insert_dir(&mut self, path: &Path) {
+        if let Some(parent) = path.parent() {
+            let entry = self.tree.entry(parent.to_path_buf()).or_insert(Directory {
+                path: parent.to_path_buf(),
+                parent: parent.parent().map(|x| x.to_path_buf()),
+                ..Default::default()
+            });
+            if entry.directories.last().map(|d| d.as_path()) != Some(path) {
+                entry.directories.push(path.to_path_buf());
+            }
+        }
+    }
+
+    /// Remove directories listed twice in `path`, its ancestors and the directories below it,
+    /// which inserting out of walk order can cause
+    fn dedup_subdirs(&mut self, path: &Path) {
+        for dir in self
+            .tree
+            .values_mut()
+            .filter(|d| d.path.starts_with(path) || path.starts_with(&d.path))
+        {
+            let mut seen = HashSet::new();
+            dir.directories.retain(|d| seen.insert(d.clone()));
+        }
+    }
+
+    /// Add a file to its directory, its type and all totals.
+    /// Directory sizes are added up to, but excluding, `stop`.
+    pub(crate) fn insert_file(&mut self, file: File, stop: Option<&Path>) {
+        self.combined_size += file.size;
+        self.combined_allocated_size += file.allocated;
+
+        // Since we are at a file level, the parent is the enclosing folder
+        if let Some(containing_dir) = file.path.parent() {
+            let ancestors: Vec<&Path> = containing_dir
+                .ancestors()
+                .take_while(|a| Some(*a) != stop)
+                .collect();
+            // Outermost first, so sources without directory entries, such as archives,
+            // still get every directory registered with its parent
+            for a in ancestors.into_iter().rev() {
+                if !self.tree.contains_key(a) {
+                    self.insert_dir(a);
+                }
+                let dir = self.tree.entry(a.to_path_buf()).or_insert(Directory {
+                    path: a.to_path_buf(),
+                    parent: a.parent().map(|x| x.to_path_buf()),
+                    ..Default::default()
+                });
+                dir.combined_size += file.size;
+                dir.combined_allocated_size += file.allocated;
+            }
+
+            let tree_dir = self
+                .tree
+                .entry(containing_dir.to_path_buf())
+                .or_insert(Directory {
+                    path: containing_dir.to_path_buf(),
+                    parent: containing_dir.parent().map(|x| x.to_path_buf()),
+                    ..Default::default()
+                });
+            tree_dir.files.push(file.clone());
+            tree_dir.size += file.size;
+            tree_dir.allocated_size += file.allocated;
+        }
+
+        if let Some(ext) = file.type_ext().map(|e| e.to_string()) {
+            let ftype = self.filetypes.entry(ext.clone()).or_insert(FileType {
+                ext,
+                size: 0,
+                files: vec![],
+            });
+            ftype.files.push(file.clone());
+            ftype.size += file.size;
+        }
+        self.files.push(file);
+    }
+
+    /// Build the sorted views and duplicates, once all files are inserted
+    pub(crate) fn build_views(&mut self) {
+        self.files_by_size = self.files_by_size();
+        self.types_by_size = self.types_by_size();
+        self.dirs_by_size = self.dirs_by_size();
+        self.duplicates = self.duplicates_from_files();
+    }
+
+    /// Add a file that was created after the scan, as if it had been scanned.
+    /// A file already known at the same path is replaced.
+    pub fn add_file(&mut self, file: File) -> Result<()> {
+        if !file.path.starts_with(&self.root) {
+            anyhow::bail!("{} is not below {}", file.path.display(), self.root.display());
+        }
+        self.detach(&file.path);
+        let stop = self.root.parent().map(|p| p.to_path_buf());
+        let dir = file.path.parent().map(|p| p.to_path_buf());
+        self.insert_file(file, stop.as_deref());
+        if let Some(dir) = dir {
+            self.dedup_subdirs(&dir);
+        }
+        self.build_views();
+        Ok(())
+    }
+
+    /// Remove a file, or a directory with everything below it, that was deleted
+    /// after the scan. Its size is subtracted from all ancestors. Return the removed files.
+    pub fn remove_path(&mut self, path: &Path) -> Vec<File> {
+        let removed = self.detach(path);
+        self.build_views();
+        removed
+    }
+
+    /// Move a file, or a directory with everything below it, that was moved or renamed
+    /// after the scan. This only updates the DirInfo, nothing is moved on disk.
+    pub fn move_path(&mut self, from: &Path, to: &Path) -> Result<()> {
+        if !to.starts_with(&self.root) {
+            anyhow::bail!("{} is not below {}", to.display(), self.root.display());
+        }
+        let known = self.tree.contains_key(from)
+            || from.parent().and_then(|p| self.tree.get(p)).is_some_and(|p| {
+                p.files.iter().any(|f| f.path == from) || p.directories.iter().any(|d| d == from)
+            });
+        if !known {
+            anyhow::bail!("{} is not known", from.display());
+        }
+        let mut moved_dirs: Vec<PathBuf> = self
+            .tree
+            .keys()
+            .filter(|p| p.starts_with(from))
+            .cloned()
+            .collect();
+        if let Some(parent) = from.parent().and_then(|p| self.tree.get(p)) {
+            // Empty directories are only listed in their parent
+            moved_dirs.extend(parent.directories.iter().filter(|d| d.as_path() == from).cloned());
+        }
+        for dir in self.tree.values().filter(|d| d.path.starts_with(from)) {
+            moved_dirs.extend(dir.directories.iter().cloned());
+        }
+        moved_dirs.sort();
+        moved_dirs.dedup();
+        // Labels found by the scan move along with their directories
+        let labels: Vec<(PathBuf, bool, Option<String>)> = self
+            .tree
+            .values()
+            .filter(|d| (d.cache || d.mount.is_some()) && d.path.starts_with(from))
+            .map(|d| (d.path.clone(), d.cache, d.mount.clone()))
+            .collect();
+
+        let relocate = |path: &Path| match path.strip_prefix(from) {
+            Ok(rel) if rel.as_os_str().is_empty() => to.to_path_buf(),
+            Ok(rel) => to.join(rel),
+            Err(_) => path.to_path_buf(),
+        };
+        let stop = self.root.parent().map(|p| p.to_path_buf());
+        for mut file in self.detach(from) {
+            let path = relocate(&file.path);
+            file.ext = path.extension().map(|x| x.to_string_lossy().to_string().to_lowercase());
+            file.path = path;
+            self.insert_file(file, stop.as_deref());
+        }
+        for dir in moved_dirs {
+            self.insert_dir(&relocate(&dir));
+        }
+        self.dedup_subdirs(to);
+        for (path, cache, mount) in labels {
+            if let Some(dir) = self.tree.get_mut(&relocate(&path)) {
+                dir.cache = cache;
+                dir.mount = mount;
+            }
+        }
+        self.build_views();
+        Ok(())
+    }
+
+    /// Remove a path from the tree, its ancestors, file types and files, but not from the sorted views
+    fn detach(&mut self, path: &Path) -> Vec<File> {
+        if let Some(parent) = path.parent().and_then(|p| self.tree.get_mut(p)) {
+            parent.directories.retain(|d| d != path);
+        }
+        let removed: Vec<File> = if self.tree.contains_key(path) {
+            let subdirs: Vec<PathBuf> = self
+                .tree
+                .keys()
+                .filter(|p| p.starts_with(path))
+                .cloned()
+                .collect();
+            subdirs
+                .iter()
+                .filter_map(|d| self.tree.remove(d))
+                .flat_map(|d| d.files)
+                .collect()
+        } else {
+            match path.parent().and_then(|p| self.tree.get_mut(p)) {
+                Some(dir) => {
+                    let (removed, kept) = dir.files.drain(..).partition(|f| f.path == path);
+                    dir.files = kept;
+                    removed
+                }
+                None => vec![],
+            }
+        };
+
+        for file in &removed {
+            self.combined_size -= file.size;
+            self.combined_allocated_size -= file.allocated;
+            if let Some(containing_dir) = file.path.parent() {
+                for a in containing_dir.ancestors() {
+                    if let Some(dir) = self.tree.get_mut(a) {
+                        // The parent of the scanned directory is in the tree, but holds no sizes
+                        dir.combined_size = dir.combined_size.saturating_sub(file.size);
+                        dir.combined_allocated_size = dir.combined_allocated_size.saturating_sub(file.allocated);
+                        if a == containing_dir {
+                            dir.size -= file.size;
+                            dir.allocated_size -= file.allocated;
+                        }
+                    }
+                }
+            }
+        }
+
+        let paths: HashSet<&Path> = removed.iter().map(|f| f.path.as_path()).collect();
+        for ftype in self.filetypes.values_mut() {
+            ftype.files.retain(|f| !paths.contains(f.path.as_path()));
+            ftype.size = ftype.files.iter().map(|f| f.size).sum();
+        }
+        self.filetypes.retain(|_, t| !t.files.is_empty());
+        self.files.retain(|f| !paths.contains(f.path.as_path()));
+        removed
+    }
+
     /// Return all duplicates
+    #[allow(dead_code)]
     fn build_duplicates_mut(&mut self) {
         self.duplicates = Self::build_duplicates(&self.duplicates);
     }
 }
 
+#[derive(Debug, Clone, Default)]
+/// Options to control what a scan collects
+pub struct ScanOptions {
+    /// Detect file types from their content. Files without an extension, or with one
+    /// their content contradicts, are then listed in `filetypes` under the detected type.
+    pub sniff_types: bool,
+    /// What to do with directories tagged as cache with a `CACHEDIR.TAG`
+    pub cache_dirs: cachedir::CacheDirs,
+    /// Only collect files matching one of these globs, or inside a directory matching one.
+    /// All files are collected if empty. See `exclude` for the syntax.
+    pub include: Vec<String>,
+    /// Leave out files and directories matching any of these globs
+    pub exclude: Vec<String>,
+    /// Names of ignore files, such as `.gitignore`, whose patterns leave out paths below
+    /// their directory. See `exclude::DEFAULT_IGNORE_FILES`.
+    pub ignore_files: Vec<String>,
+    /// Stay on the filesystem of the scanned path, like `du -x`
+    pub one_file_system: bool,
+    /// Leave out filesystems such as `proc` and `sysfs`, see `mounts::PSEUDO_FILESYSTEMS`
+    pub skip_pseudo_filesystems: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Why a path was left out of a scan
+pub enum SkipReason {
+    /// Tagged as cache, see `cachedir`
+    CacheDir,
+    /// Matched this exclude glob
+    Excluded(String),
+    /// Matched a pattern in this ignore file
+    Ignored(PathBuf),
+    /// A mount point of a filesystem of this type, left out as the scan stays on one filesystem
+    OtherFileSystem(String),
+    /// A mount point of a pseudo filesystem of this type
+    PseudoFileSystem(String),
+}
+
 /// Scan a directory, calling callback with DirInfo periodically
 pub fn scan_callback<P: AsRef<Path>, F: Fn(&DirInfo)>(
     source: P,
     callback: F,
     update_rate_ms: u128,
 ) -> DirInfo {
-    // pub fn scan<P: AsRef<Path>>(source: P) -> DirInfo {
+    scan_with_options(source, &ScanOptions::default(), callback, update_rate_ms)
+}
+
+/// Scan a directory using options, calling callback with DirInfo periodically
+pub fn scan_with_options<P: AsRef<Path>, F: Fn(&DirInfo)>(
+    source: P,
+    options: &ScanOptions,
+    callback: F,
+    update_rate_ms: u128,
+) -> DirInfo {
     let mut dirinfo = DirInfo::new();
+    dirinfo.atime_mode = mounts::atime_mode(&source);
+    dirinfo.root = source.as_ref().to_path_buf();
     let mut updatetimer = std::time::Instant::now();
 
-    WalkDir::new(&source)
+    let mut tagged: Vec<PathBuf> = vec![];
+    let mut filter = exclude::Filter::new(source.as_ref(), options);
+    let mounts = mounts::mounts();
+    let mut mount_points: Vec<(PathBuf, String)> = vec![];
+    // Devices of the directories above the current entry, the root first
+    let mut devices: Vec<Option<u64>> = vec![];
+    // A manual loop, so that excluded directories can be skipped
+    let mut walker = WalkDir::new(&source)
         // .skip_hidden(false)
-        .into_iter()
-        .flatten()
-        .for_each(|x| {
-            // TODO this should not include dirs outside scan root
-            // TODO: if/else to avoid both is_dir and is_file
-            if x.path().is_dir() {
-                if let Some(parent) = x.path().parent() {
-                    //debug!("{:?} parent: {:?}", x.path(), &parent);
-
-                    let entry = dirinfo
-                        .tree
-                        .entry(parent.to_path_buf())
-                        .or_insert(Directory {
-                            path: parent.to_path_buf(),
-                            ..Default::default()
-                        });
-
-                    entry.directories.push(x.path().to_path_buf());
+        .into_iter();
+    while let Some(entry) = walker.next() {
+        let x = match entry {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        // TODO this should not include dirs outside scan root
+        // TODO: if/else to avoid both is_dir and is_file
+        let is_dir = x.path().is_dir();
+        if x.depth() > 0 {
+            if let Some(reason) = filter.skip(x.path(), is_dir) {
+                // Symlinks to directories are not descended into, so there is nothing to skip
+                if x.file_type().is_dir() {
+                    walker.skip_current_dir();
                 }
+                dirinfo.skipped.push((x.path().to_path_buf(), reason));
+                continue;
             }
-            // if x.path().is_file() {
-            // Assume it's a file
-            else {
-                let ext_string: Option<String> = x
-                    .path()
-                    .extension()
-                    .map(|x| x.to_string_lossy().to_string().to_lowercase());
-
-                // Make sure metadata is available for the file
-                if let Ok(meta) = x.metadata() {
-                    let size = meta.len();
-                    dirinfo.combined_size += size;
-                    let hash = hash_file(&x.path()).unwrap_or_default();
-                    debug!("{:?} is {}", x.path(), hash);
-                    let file = File {
-                        size,
-                        ext: ext_string.clone(),
-                        path: x.path().to_path_buf(),
-                        modified: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
-                        hash
-                    };
-                    // Since we are at a file level, the parent is the enclosing folder
-                    if let Some(containing_dir) = x.path().parent() {
-                        // let p = parent.to_path_buf();
-                        let tree_dir =
-                            dirinfo
-                                .tree
-                                .entry(containing_dir.to_path_buf())
-                                .or_insert(Directory {
-                                    path: containing_dir.to_path_buf(),
-                                    parent: containing_dir.parent().map(|x| x.to_path_buf()),
-                                    ..Default::default()
-                                });
-                        tree_dir.files.push(file.clone());
-                        tree_dir.size += size;
-
-                        for a in containing_dir.ancestors() {
-                            // debug!("Adding {:?} to {}", x.path().display(), a.display());
-
-                            if let Some(p) = source.as_ref().parent() {
-                                if a == p {
-                                    break;
-                                }
-                            }
-                            dirinfo
-                                .tree
-                                .entry(a.to_path_buf())
-                                .or_insert(Directory {
-                                    path: a.to_path_buf(),
-                                    parent: a.parent().map(|x| x.to_path_buf()),
-                                    ..Default::default()
-                                })
-                                .combined_size += size;
-                        }
-                    }
-                    if let Some(ext) = ext_string {
-                        let ftype = dirinfo.filetypes.entry(ext.clone()).or_insert(FileType {
-                            ext,
-                            size: 0,
-                            files: vec![],
-                        });
-                        ftype.files.push(file.clone());
-                        ftype.size += file.size;
-                    }
-                    dirinfo.files.push(file);
+        }
+        if is_dir {
+            let device = x.metadata().ok().and_then(|m| device(&m));
+            devices.truncate(x.depth());
+            // Only a change of device needs a look at the mounts
+            let mount = if devices.last().map_or(true, |d| *d != device) {
+                mount_point(x.path(), &mounts)
+            } else {
+                None
+            };
+            if x.depth() > 0 {
+                if let Some(reason) = filesystem_skip(options, device, devices[0], mount) {
+                    dirinfo.skipped.push((x.path().to_path_buf(), reason));
+                    walker.skip_current_dir();
+                    continue;
                 }
             }
-
-            // do sth here as callback
-            if updatetimer.elapsed().as_millis() > update_rate_ms {
-                callback(&dirinfo);
-                updatetimer = std::time::Instant::now();
+            devices.push(device);
+            if let Some(mount) = mount {
+                mount_points.push((x.path().to_path_buf(), mount.fs_type.clone()));
+            }
+            // A symlink to a tagged directory is not a cache itself
+            if options.cache_dirs != cachedir::CacheDirs::Ignore
+                && x.file_type().is_dir()
+                && cachedir::is_tagged(x.path())
+            {
+                if options.cache_dirs == cachedir::CacheDirs::Exclude {
+                    dirinfo.skipped.push((x.path().to_path_buf(), SkipReason::CacheDir));
+                    walker.skip_current_dir();
+                    continue;
+                }
+                tagged.push(x.path().to_path_buf());
+            }
+            filter.enter(x.path());
+            dirinfo.insert_dir(x.path());
+        }
+        // if x.path().is_file() {
+        // Assume it's a file
+        else if filter.include(x.path()) {
+            // Make sure metadata is available for the file
+            if let Ok(meta) = x.metadata() {
+                let file = File::read(x.path(), &meta, options.sniff_types);
+                dirinfo.insert_file(file, source.as_ref().parent());
             }
-        });
+        }
 
-    dirinfo.files_by_size = dirinfo.files_by_size();
-    dirinfo.types_by_size = dirinfo.types_by_size();
-    dirinfo.dirs_by_size = dirinfo.dirs_by_size();
-    dirinfo.duplicates = dirinfo.duplicates_from_files();
+        // do sth here as callback
+        if updatetimer.elapsed().as_millis() > update_rate_ms {
+            callback(&dirinfo);
+            updatetimer = std::time::Instant::now();
+        }
+    }
+
+    for path in tagged {
+        if let Some(dir) = dirinfo.tree.get_mut(&path) {
+            dir.cache = true;
+        }
+    }
+    for (path, fs_type) in mount_points {
+        if let Some(dir) = dirinfo.tree.get_mut(&path) {
+            dir.mount = Some(fs_type);
+        }
+    }
+    dirinfo.build_views();
 
     dirinfo
 }
 
 /// Scan a root path and produce a DirInfo
 pub fn scan<P: AsRef<Path>>(source: P) -> DirInfo {
-    scan_callback(source, |_| {}, std::u128::MAX)
+    scan_callback(source, |_| {}, u128::MAX)
 }
 
-pub fn scan_archive<P: AsRef<Path>>(source: P) -> DirInfo {
+/// Scan a zip archive and produce a DirInfo. Fails if it can not be opened or read as zip.
+pub fn scan_archive<P: AsRef<Path>>(source: P) -> Result<DirInfo> {
     let mut dirinfo = DirInfo::new();
+    // Entries are relative to the archive
+    dirinfo.root = PathBuf::new();
 
-    let zipfile = fs::File::open(&source.as_ref()).unwrap();
+    let source = source.as_ref();
+    let zipfile = fs::File::open(source).with_context(|| source.display().to_string())?;
 
-    let mut archive = zip::ZipArchive::new(zipfile).unwrap();
+    let mut archive = zip::ZipArchive::new(zipfile)
+        .with_context(|| format!("{} is not a zip archive", source.display()))?;
 
     for i in 0..archive.len() {
-        let mut zip_entry = archive.by_index(i).unwrap();
+        let mut zip_entry = archive.by_index(i)?;
 
         if zip_entry.is_dir() {
         } else {
@@ -360,86 +774,124 @@ pub fn scan_archive<P: AsRef<Path>>(source: P) -> DirInfo {
             
             let hash = hash_bytes(&buf);
             info!("{} {}", zip_entry.name(), hash);
-            dirinfo.combined_size += size;
             let file = File {
                 size,
-                ext: ext_string.clone(),
+                allocated: size,
+                ext: ext_string,
                 path: Path::new(zip_entry.name()).to_path_buf(),
                 modified: SystemTime::now(),
-                hash
+                accessed: None,
+                created: None,
+                uid: None,
+                gid: None,
+                mode: zip_entry.unix_mode(),
+                mime: None,
+                detected_ext: None,
+                hash,
+                inode: None,
             };
 
             dirinfo
                 .duplicates
-                .entry(zip_entry.compressed_size() as u64)
+                .entry(zip_entry.compressed_size())
                 .and_modify(|e| e.push(file.clone()))
                 .or_insert(vec![file.clone()]);
 
-            if let Some(containing_dir) = Path::new(zip_entry.name()).parent() {
-                let tree_dir =
-                    dirinfo
-                        .tree
-                        .entry(containing_dir.to_path_buf())
-                        .or_insert(Directory {
-                            path: containing_dir.to_path_buf(),
-                            parent: containing_dir.parent().map(|x| x.to_path_buf()),
-                            ..Default::default()
-                        });
-                tree_dir.files.push(file.clone());
-                tree_dir.size += size;
+            dirinfo.insert_file(file, None);
+        }
+    }
 
-                for a in containing_dir.ancestors() {
-                    // debug!("Adding {:?} to {}", x.path().display(), a.display());
+    dirinfo.build_views();
 
-                    if let Some(p) = source.as_ref().parent() {
-                        if a == p {
-                            break;
-                        }
-                    }
-                    dirinfo
-                        .tree
-                        .entry(a.to_path_buf())
-                        .or_insert(Directory {
-                            path: a.to_path_buf(),
-                            parent: a.parent().map(|x| x.to_path_buf()),
-                            ..Default::default()
-                        })
-                        .combined_size += size;
-                }
-            }
+    // dirinfo.build_duplicates_mut();
 
-            if let Some(ext) = ext_string {
-                let ftype = dirinfo.filetypes.entry(ext.clone()).or_insert(FileType {
-                    ext,
-                    size: 0,
-                    files: vec![],
-                });
-                ftype.files.push(file.clone());
-                ftype.size += file.size;
-            }
-            dirinfo.files.push(file.clone());
-        }
+    Ok(dirinfo)
+}
+
+
+/// Return why a directory is left out because of its filesystem, if it is.
+/// `device` is the device of the directory, `root_device` the one of the scanned path
+/// and `mount` the mount at the directory, if it is a mount point.
+fn filesystem_skip(
+    options: &ScanOptions,
+    device: Option<u64>,
+    root_device: Option<u64>,
+    mount: Option<&mounts::Mount>,
+) -> Option<SkipReason> {
+    let fs_type = || mount.map(|m| m.fs_type.clone()).unwrap_or_default();
+    if options.one_file_system && device != root_device {
+        Some(SkipReason::OtherFileSystem(fs_type()))
+    } else if options.skip_pseudo_filesystems && mount.is_some_and(|m| m.is_pseudo()) {
+        Some(SkipReason::PseudoFileSystem(fs_type()))
+    } else {
+        None
     }
+}
 
-    dirinfo.files_by_size = dirinfo.files_by_size();
-    dirinfo.types_by_size = dirinfo.types_by_size();
-    dirinfo.dirs_by_size = dirinfo.dirs_by_size();
-    dirinfo.duplicates = dirinfo.duplicates_from_files();
+/// Return the mount mounted exactly at `path`
+fn mount_point<'a>(path: &Path, mounts: &'a [mounts::Mount]) -> Option<&'a mounts::Mount> {
+    let path = path.canonicalize().ok()?;
+    mounts::mount_of(&path, mounts).filter(|m| m.mount_point == path)
+}
 
-    // dirinfo.build_duplicates_mut();
+#[cfg(unix)]
+fn device(meta: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.dev())
+}
 
-    dirinfo
+#[cfg(not(unix))]
+fn device(_meta: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+fn allocated_size(meta: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn allocated_size(meta: &fs::Metadata) -> u64 {
+    meta.len()
 }
 
+#[cfg(unix)]
+fn ownership(meta: &fs::Metadata) -> (Option<u32>, Option<u32>, Option<u32>) {
+    use std::os::unix::fs::MetadataExt;
+    (Some(meta.uid()), Some(meta.gid()), Some(meta.mode()))
+}
+
+#[cfg(unix)]
+fn inode(meta: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn inode(_meta: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(not(unix))]
+fn ownership(_meta: &fs::Metadata) -> (Option<u32>, Option<u32>, Option<u32>) {
+    (None, None, None)
+}
 
-fn hash_file(file: &Path) -> Result<u64> {
+/// Hash a file, optionally detecting its type from the same read
+fn hash_file(file: &Path, sniff: bool) -> Result<(u64, Option<magic::Magic>)> {
     let f = std::fs::File::open(file)?;
     let mut r = BufReader::new(f);
     let mut buf: Vec<u8> = vec![];
     r.read_to_end(&mut buf)?;
     // r.(&mut buf)?;
     // Ok(hash_bytes(&buf))
-    Ok(hash_bytes(&buf))
+    let magic = if sniff {
+        magic::sniff(&buf[..buf.len().min(magic::SNIFF_LEN)])
+    } else {
+        None
+    };
+    Ok((hash_bytes(&buf), magic))
 }
 
 fn hash_bytes(b: &[u8]) -> u64 {
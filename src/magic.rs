@@ -0,0 +1,131 @@
+//! Content based file type detection using magic bytes.
+
+/// Number of leading bytes needed to detect all known types
+pub const SNIFF_LEN: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A detected file type
+pub struct Magic {
+    /// MIME type, such as `image/png`
+    pub mime: &'static str,
+    /// The extension used for this type, such as `png`
+    pub ext: &'static str,
+}
+
+/// Detected types that other formats are built on, such as office documents and `jar`
+/// files on zip. They never replace the extension of a file.
+pub const CONTAINER_EXTENSIONS: &[&str] = &["zip", "gz", "script", "exe", "elf", "macho"];
+
+/// Signatures: offset, bytes and the type they identify. First match wins.
+const SIGNATURES: &[(usize, &[u8], &str, &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png", "png"),
+    (0, b"\xff\xd8\xff", "image/jpeg", "jpg"),
+    (0, b"GIF87a", "image/gif", "gif"),
+    (0, b"GIF89a", "image/gif", "gif"),
+    (0, b"%PDF-", "application/pdf", "pdf"),
+    (0, b"SQLite format 3\0", "application/vnd.sqlite3", "sqlite"),
+    (0, b"PK\x03\x04", "application/zip", "zip"),
+    (0, b"PK\x05\x06", "application/zip", "zip"),
+    (0, b"\x1f\x8b", "application/gzip", "gz"),
+    (0, b"BZh", "application/x-bzip2", "bz2"),
+    (0, b"\xfd7zXZ\0", "application/x-xz", "xz"),
+    (0, b"\x28\xb5\x2f\xfd", "application/zstd", "zst"),
+    (
+        0,
+        b"7z\xbc\xaf\x27\x1c",
+        "application/x-7z-compressed",
+        "7z",
+    ),
+    (257, b"ustar", "application/x-tar", "tar"),
+    (0, b"\x1a\x45\xdf\xa3", "video/x-matroska", "mkv"),
+    (0, b"OggS", "audio/ogg", "ogg"),
+    (0, b"fLaC", "audio/flac", "flac"),
+    (0, b"ID3", "audio/mpeg", "mp3"),
+    (
+        0,
+        b"MZ",
+        "application/vnd.microsoft.portable-executable",
+        "exe",
+    ),
+    (0, b"\xcf\xfa\xed\xfe", "application/x-mach-binary", "macho"),
+    (0, b"\xca\xfe\xba\xbe", "application/x-mach-binary", "macho"),
+    (0, b"#!", "text/x-script", "script"),
+];
+
+/// Detect the type of a file from its first bytes
+pub fn sniff(bytes: &[u8]) -> Option<Magic> {
+    if let Some(magic) = sniff_elf(bytes) {
+        return Some(magic);
+    }
+    if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" {
+        return match &bytes[8..12] {
+            b"WEBP" => Some(Magic {
+                mime: "image/webp",
+                ext: "webp",
+            }),
+            b"WAVE" => Some(Magic {
+                mime: "audio/wav",
+                ext: "wav",
+            }),
+            b"AVI " => Some(Magic {
+                mime: "video/x-msvideo",
+                ext: "avi",
+            }),
+            _ => None,
+        };
+    }
+    if let Some(magic) = sniff_ftyp(bytes) {
+        return Some(magic);
+    }
+    SIGNATURES
+        .iter()
+        .find(|(offset, sig, _, _)| bytes.get(*offset..offset + sig.len()) == Some(*sig))
+        .map(|(_, _, mime, ext)| Magic { mime, ext })
+}
+
+/// ISO base media files share the `ftyp` box and are told apart by their major brand
+fn sniff_ftyp(bytes: &[u8]) -> Option<Magic> {
+    if bytes.get(4..8)? != b"ftyp" {
+        return None;
+    }
+    let (mime, ext) = match bytes.get(8..12)? {
+        b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" | b"mif1" | b"msf1" => {
+            ("image/heic", "heic")
+        }
+        b"avif" | b"avis" => ("image/avif", "avif"),
+        b"M4A " | b"M4B " => ("audio/mp4", "m4a"),
+        b"qt  " => ("video/quicktime", "mov"),
+        b"crx " => ("image/x-canon-cr3", "cr3"),
+        brand if brand.starts_with(b"3gp") || brand.starts_with(b"3g2") => ("video/3gpp", "3gp"),
+        _ => ("video/mp4", "mp4"),
+    };
+    Some(Magic { mime, ext })
+}
+
+/// ELF files are told apart by their object type, so core dumps are not counted as binaries
+fn sniff_elf(bytes: &[u8]) -> Option<Magic> {
+    if bytes.get(0..4)? != b"\x7fELF" {
+        return None;
+    }
+    let e_type = [*bytes.get(16)?, *bytes.get(17)?];
+    // EI_DATA: 1 is little endian, 2 is big endian
+    let e_type = match bytes.get(5)? {
+        2 => u16::from_be_bytes(e_type),
+        _ => u16::from_le_bytes(e_type),
+    };
+    Some(match e_type {
+        1 => Magic {
+            mime: "application/x-object",
+            ext: "o",
+        },
+        4 => Magic {
+            mime: "application/x-coredump",
+            ext: "core",
+        },
+        // Executables, PIE executables and shared libraries can not be told apart by type alone
+        _ => Magic {
+            mime: "application/x-executable",
+            ext: "elf",
+        },
+    })
+}
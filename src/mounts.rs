@@ -0,0 +1,215 @@
+//! Mounted filesystems, as listed in `/proc/self/mountinfo`.
+
+use crate::DirInfo;
+use std::path::{Path, PathBuf};
+
+/// Filesystems that do not hold files of their own, such as kernel interfaces.
+/// `tmpfs` and `ramfs` are left out, as they often hold `/tmp` and other user data.
+pub const PSEUDO_FILESYSTEMS: &[&str] = &[
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "cgroup",
+    "cgroup2",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "devtmpfs",
+    "efivarfs",
+    "fusectl",
+    "hugetlbfs",
+    "mqueue",
+    "nsfs",
+    "proc",
+    "pstore",
+    "securityfs",
+    "sysfs",
+    "tracefs",
+];
+
+#[derive(Debug, Clone, Default)]
+/// A mounted filesystem
+pub struct Mount {
+    /// Where the filesystem is mounted
+    pub mount_point: PathBuf,
+    /// Filesystem type, such as `ext4` or `tmpfs`
+    pub fs_type: String,
+    /// Mount source, such as `/dev/sda1`
+    pub source: String,
+    /// Per-mount options, such as `rw` or `noatime`
+    pub options: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// How access times are updated on a filesystem
+pub enum AtimeMode {
+    /// Every read updates the access time
+    Strict,
+    /// Access times are only updated if older than the modification time or a day
+    Relatime,
+    /// Access times are never updated
+    Noatime,
+    /// The mount could not be determined
+    #[default]
+    Unknown,
+}
+
+impl AtimeMode {
+    /// Whether access times can be used to find data nobody reads anymore
+    pub fn is_reliable(&self) -> bool {
+        *self == AtimeMode::Strict
+    }
+}
+
+impl Mount {
+    /// Parse a single line of `/proc/self/mountinfo`
+    pub fn parse(line: &str) -> Option<Mount> {
+        let mut fields = line.split_whitespace();
+        let mount_point = fields.nth(4)?;
+        let options = fields.next()?;
+        // Optional fields are terminated by a single dash
+        let mut fields = fields.skip_while(|f| *f != "-").skip(1);
+        let fs_type = fields.next()?;
+        let source = fields.next().unwrap_or_default();
+
+        Some(Mount {
+            mount_point: PathBuf::from(unescape(mount_point)),
+            fs_type: fs_type.to_string(),
+            source: unescape(source),
+            options: options.split(',').map(|o| o.to_string()).collect(),
+        })
+    }
+
+    /// Whether this filesystem does not store data on a disk, see `PSEUDO_FILESYSTEMS`
+    pub fn is_pseudo(&self) -> bool {
+        PSEUDO_FILESYSTEMS.contains(&self.fs_type.as_str())
+    }
+
+    /// How access times are recorded on this mount
+    pub fn atime_mode(&self) -> AtimeMode {
+        if self.options.iter().any(|o| o == "noatime") {
+            AtimeMode::Noatime
+        } else if self.options.iter().any(|o| o == "relatime") {
+            AtimeMode::Relatime
+        } else {
+            AtimeMode::Strict
+        }
+    }
+}
+
+/// Return all mounts of the current process. Empty if mountinfo is not available.
+pub fn mounts() -> Vec<Mount> {
+    std::fs::read_to_string("/proc/self/mountinfo")
+        .map(|s| s.lines().filter_map(Mount::parse).collect())
+        .unwrap_or_default()
+}
+
+/// Return the mount a path is located on
+pub fn mount_of<P: AsRef<Path>>(path: P, mounts: &[Mount]) -> Option<&Mount> {
+    let path = path.as_ref().canonicalize().ok()?;
+    deepest_mount(&path, mounts)
+}
+
+/// Return the mount a path is located on without following it if it is a symlink,
+/// so a link is found on the filesystem holding the link rather than its target
+pub fn mount_of_link<P: AsRef<Path>>(path: P, mounts: &[Mount]) -> Option<&Mount> {
+    let path = path.as_ref();
+    let path = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            parent.canonicalize().ok()?.join(name)
+        }
+        _ => path.canonicalize().ok()?,
+    };
+    deepest_mount(&path, mounts)
+}
+
+fn deepest_mount<'a>(path: &Path, mounts: &'a [Mount]) -> Option<&'a Mount> {
+    mounts
+        .iter()
+        .filter(|m| path.starts_with(&m.mount_point))
+        // Later mounts shadow earlier ones on the same mount point
+        .max_by_key(|m| m.mount_point.components().count())
+}
+
+/// How access times are recorded for a path
+pub fn atime_mode<P: AsRef<Path>>(path: P) -> AtimeMode {
+    mount_of(path, &mounts())
+        .map(|m| m.atime_mode())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Space used on one filesystem within a scan
+pub struct FilesystemUsage {
+    /// The scanned path or a mount point below it
+    pub path: PathBuf,
+    /// Filesystem type, if known
+    pub fs_type: Option<String>,
+    /// Size of the files on this filesystem, without filesystems mounted below it
+    pub size: u64,
+}
+
+impl DirInfo {
+    /// Return the space used on each filesystem of the scan, largest first.
+    /// Mount points are those labelled in `Directory::mount` by the scan.
+    pub fn filesystem_usage(&self) -> Vec<FilesystemUsage> {
+        let root = match self.tree.get(&self.root) {
+            Some(root) => root,
+            None => return vec![],
+        };
+        let mut usage = vec![FilesystemUsage {
+            path: self.root.clone(),
+            fs_type: root
+                .mount
+                .clone()
+                .or_else(|| mount_of(&self.root, &mounts()).map(|m| m.fs_type.clone())),
+            size: root.combined_size,
+        }];
+        let mut mount_points: Vec<_> = self
+            .tree
+            .values()
+            .filter(|d| d.mount.is_some() && d.path != self.root)
+            .collect();
+        mount_points.sort_by(|a, b| a.path.cmp(&b.path));
+        for dir in mount_points {
+            // Sorted paths put every mount point after the ones it is below
+            if let Some(outer) = usage
+                .iter_mut()
+                .rev()
+                .find(|u| dir.path.starts_with(&u.path))
+            {
+                outer.size -= dir.combined_size;
+            }
+            usage.push(FilesystemUsage {
+                path: dir.path.clone(),
+                fs_type: dir.mount.clone(),
+                size: dir.combined_size,
+            });
+        }
+        usage.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        usage
+    }
+}
+
+/// Decode the octal escapes mountinfo uses for whitespace and backslashes
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let code: String = chars.clone().take(3).collect();
+            if let Ok(b) = u8::from_str_radix(&code, 8) {
+                out.push(b as char);
+                chars.nth(2);
+                continue;
+            }
+        }
+        out.push(c);
+    }
+    out
+}
@@ -0,0 +1,149 @@
+//! Import and export of ncdu's JSON dump format, as written by `ncdu -o` and read by `ncdu -f`.
+//!
+//! A dump is `[1, 2, {metadata}, root]`, where every directory is an array
+//! of its own info object followed by its files (objects) and subdirectories (arrays).
+
+use crate::{DirInfo, File};
+use anyhow::{anyhow, bail, Result};
+use serde_json::{json, Map, Value};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Write the tree below `root` as an ncdu dump
+pub fn write_ncdu<W: Write>(info: &DirInfo, root: &Path, mut w: W) -> Result<()> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let meta = json!({
+        "progname": env!("CARGO_PKG_NAME"),
+        "progver": env!("CARGO_PKG_VERSION"),
+        "timestamp": timestamp,
+    });
+    writeln!(w, "[1,2,{},", meta)?;
+    // ncdu shows the root with its full path
+    let name = root
+        .canonicalize()
+        .unwrap_or_else(|_| root.to_path_buf())
+        .display()
+        .to_string();
+    write_dir(info, root, &name, &mut w)?;
+    writeln!(w, "]")?;
+    Ok(())
+}
+
+fn write_dir<W: Write>(info: &DirInfo, path: &Path, name: &str, w: &mut W) -> Result<()> {
+    write!(w, "[{}", json!({ "name": name }))?;
+    if let Some(dir) = info.tree.get(path) {
+        for file in &dir.files {
+            let mut entry = Map::new();
+            entry.insert("name".into(), json!(file_name(&file.path)));
+            entry.insert("asize".into(), json!(file.size));
+            entry.insert("dsize".into(), json!(file.allocated));
+            if let Ok(mtime) = file.modified.duration_since(UNIX_EPOCH) {
+                entry.insert("mtime".into(), json!(mtime.as_secs()));
+            }
+            if let Some(uid) = file.uid {
+                entry.insert("uid".into(), json!(uid));
+            }
+            if let Some(gid) = file.gid {
+                entry.insert("gid".into(), json!(gid));
+            }
+            if let Some(mode) = file.mode {
+                entry.insert("mode".into(), json!(mode));
+            }
+            write!(w, ",\n{}", Value::Object(entry))?;
+        }
+        for subdir in &dir.directories {
+            writeln!(w, ",")?;
+            write_dir(info, subdir, &file_name(subdir), w)?;
+        }
+    }
+    write!(w, "]")?;
+    Ok(())
+}
+
+/// Read an ncdu dump into a DirInfo. Dumps carry no content hashes, so files get a hash of 0
+/// and no duplicates are reported.
+pub fn read_ncdu<R: Read>(r: R) -> Result<DirInfo> {
+    let dump: Value = serde_json::from_reader(r)?;
+    let root = match dump.as_array().map(|a| a.as_slice()) {
+        Some([major, _minor, _meta, root, ..]) if major.as_u64() == Some(1) => root,
+        _ => bail!("Not an ncdu dump"),
+    };
+
+    let mut info = DirInfo::new();
+    let root_path = PathBuf::from(dir_name(root)?);
+    info.root = root_path.clone();
+    read_dir(&mut info, root, &root_path, root_path.parent())?;
+    info.build_views();
+    Ok(info)
+}
+
+fn read_dir(info: &mut DirInfo, dir: &Value, path: &Path, stop: Option<&Path>) -> Result<()> {
+    info.insert_dir(path);
+    for entry in dir.as_array().into_iter().flatten().skip(1) {
+        if entry.is_array() {
+            let subdir = path.join(entry_name(dir_name(entry)?, path)?);
+            read_dir(info, entry, &subdir, stop)?;
+            continue;
+        }
+        // Excluded entries have not been scanned by ncdu
+        if entry.get("excluded").is_some() {
+            continue;
+        }
+        let name = entry
+            .get("name")
+            .and_then(|n| n.as_str())
+            .ok_or_else(|| anyhow!("Entry without name in {}", path.display()))?;
+        let name = entry_name(name, path)?;
+        let field = |key: &str| entry.get(key).and_then(|v| v.as_u64());
+        let size = field("asize").unwrap_or_default();
+        let file_path = path.join(name);
+        let file = File {
+            size,
+            allocated: field("dsize").unwrap_or(size),
+            ext: file_path
+                .extension()
+                .map(|x| x.to_string_lossy().to_string().to_lowercase()),
+            modified: field("mtime")
+                .map(|t| UNIX_EPOCH + Duration::from_secs(t))
+                .unwrap_or(UNIX_EPOCH),
+            accessed: None,
+            created: None,
+            uid: field("uid").map(|v| v as u32),
+            gid: field("gid").map(|v| v as u32),
+            mode: field("mode").map(|v| v as u32),
+            mime: None,
+            detected_ext: None,
+            hash: 0,
+            inode: None,
+            path: file_path,
+        };
+        info.insert_file(file, stop);
+    }
+    Ok(())
+}
+
+fn dir_name(dir: &Value) -> Result<&str> {
+    dir.get(0)
+        .and_then(|d| d.get("name"))
+        .and_then(|n| n.as_str())
+        .ok_or_else(|| anyhow!("Directory without name"))
+}
+
+/// Check that the name of an entry is a single name, such that joining it stays inside `parent`
+fn entry_name<'a>(name: &'a str, parent: &Path) -> Result<&'a str> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(n)), None) if n == name => Ok(name),
+        _ => bail!("Invalid name {:?} in {}", name, parent.display()),
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,175 @@
+//! Pairs of directories that hold some of the same files, such as diverged copies.
+
+use crate::duplicates::DuplicateGroup;
+use crate::{DirInfo, File};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Content found in more directories than this is left out, as it is likely
+/// boilerplate like license files that would pair up every directory with every other.
+pub const MAX_DIRECTORIES: usize = 64;
+
+/// Files listed on each side of an overlap. Further files are only counted.
+pub const MAX_LISTED_FILES: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Two directories sharing files, including the files in their subdirectories
+pub struct DirectoryOverlap {
+    pub first: PathBuf,
+    pub second: PathBuf,
+    /// Size of the content found in both, counted once
+    pub shared_size: u64,
+    /// Number of distinct contents found in both
+    pub shared_count: usize,
+    /// Files below `first` with content that is not below `second`, by path,
+    /// at most `MAX_LISTED_FILES`
+    pub only_first: Vec<PathBuf>,
+    /// Number of files below `first` with content that is not below `second`
+    pub only_first_count: usize,
+    /// Files below `second` with content that is not below `first`, by path,
+    /// at most `MAX_LISTED_FILES`
+    pub only_second: Vec<PathBuf>,
+    /// Number of files below `second` with content that is not below `first`
+    pub only_second_count: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Overlapping directories, most shared bytes first
+pub struct OverlapReport {
+    pub overlaps: Vec<DirectoryOverlap>,
+    /// Content left out as it is in more than `MAX_DIRECTORIES` directories
+    pub widespread: Vec<DuplicateGroup>,
+}
+
+impl DirInfo {
+    /// Return pairs of directories with files in common, most shared bytes first.
+    /// Copies are compared at the same level above them, so `a/2020/x.jpg` and
+    /// `b/2020/x.jpg` pair up `a/2020` with `b/2020` and `a` with `b`.
+    /// Empty files are ignored.
+    pub fn overlapping_directories(&self) -> OverlapReport {
+        let mut shared: HashMap<(&Path, &Path), (u64, usize)> = HashMap::new();
+        let mut widespread = vec![];
+        for (hash, files) in &self.duplicates {
+            let size = files.first().map(|f| f.size).unwrap_or_default();
+            if size == 0 {
+                continue;
+            }
+            let mut dirs: Vec<&Path> = files.iter().filter_map(|f| f.path.parent()).collect();
+            dirs.sort();
+            dirs.dedup();
+            if dirs.len() > MAX_DIRECTORIES {
+                widespread.push(DuplicateGroup::new(*hash, files));
+                continue;
+            }
+            // Every pair shares this content once, however many copies lead to it
+            let mut pairs: HashSet<(&Path, &Path)> = HashSet::new();
+            for (i, first) in dirs.iter().enumerate() {
+                for second in &dirs[i + 1..] {
+                    self.ancestor_pairs(first, second, &mut pairs);
+                }
+            }
+            for pair in pairs {
+                let entry = shared.entry(pair).or_default();
+                entry.0 += size;
+                entry.1 += 1;
+            }
+        }
+
+        let subtrees = self.subtrees(shared.keys().flat_map(|(a, b)| [*a, *b]).collect());
+        let mut overlaps: Vec<DirectoryOverlap> = shared
+            .into_iter()
+            .map(|((first, second), (shared_size, shared_count))| {
+                let (only_first, only_first_count) = unique_files(&subtrees, first, second);
+                let (only_second, only_second_count) = unique_files(&subtrees, second, first);
+                DirectoryOverlap {
+                    first: first.to_path_buf(),
+                    second: second.to_path_buf(),
+                    shared_size,
+                    shared_count,
+                    only_first,
+                    only_first_count,
+                    only_second,
+                    only_second_count,
+                }
+            })
+            .collect();
+        overlaps.sort_by(|a, b| {
+            b.shared_size
+                .cmp(&a.shared_size)
+                .then_with(|| a.first.cmp(&b.first))
+                .then_with(|| a.second.cmp(&b.second))
+        });
+        widespread.sort_by(|a, b| b.wasted.cmp(&a.wasted).then(a.paths.cmp(&b.paths)));
+        OverlapReport {
+            overlaps,
+            widespread,
+        }
+    }
+
+    /// Add `first` and `second` and their ancestors at the same level, up to
+    /// the root or a directory containing the other
+    fn ancestor_pairs<'a>(
+        &self,
+        mut first: &'a Path,
+        mut second: &'a Path,
+        pairs: &mut HashSet<(&'a Path, &'a Path)>,
+    ) {
+        while !first.starts_with(second) && !second.starts_with(first) {
+            pairs.insert(if first < second {
+                (first, second)
+            } else {
+                (second, first)
+            });
+            match (first.parent(), second.parent()) {
+                (Some(a), Some(b)) if a.starts_with(&self.root) && b.starts_with(&self.root) => {
+                    first = a;
+                    second = b;
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// The files below each of `dirs` with their hashes, collected in one pass over all files
+    fn subtrees<'a>(&'a self, dirs: HashSet<&'a Path>) -> HashMap<&'a Path, Subtree<'a>> {
+        let mut subtrees: HashMap<&Path, Subtree> = HashMap::new();
+        for file in &self.files {
+            for dir in file.path.ancestors().skip(1).filter(|a| dirs.contains(a)) {
+                let subtree = subtrees.entry(dir).or_default();
+                subtree.hashes.insert(file.hash);
+                subtree.files.push(file);
+            }
+        }
+        for subtree in subtrees.values_mut() {
+            subtree.files.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+        subtrees
+    }
+}
+
+#[derive(Default)]
+struct Subtree<'a> {
+    files: Vec<&'a File>,
+    hashes: HashSet<u64>,
+}
+
+/// Files below `dir` whose content is not below `other`, the first `MAX_LISTED_FILES`
+/// of them and their number
+fn unique_files(
+    subtrees: &HashMap<&Path, Subtree>,
+    dir: &Path,
+    other: &Path,
+) -> (Vec<PathBuf>, usize) {
+    let (dir, other) = match (subtrees.get(dir), subtrees.get(other)) {
+        (Some(dir), Some(other)) => (dir, other),
+        _ => return (vec![], 0),
+    };
+    let mut unique = dir.files.iter().filter(|f| !other.hashes.contains(&f.hash));
+    let listed: Vec<PathBuf> = unique
+        .by_ref()
+        .take(MAX_LISTED_FILES)
+        .map(|f| f.path.clone())
+        .collect();
+    let count = listed.len() + unique.count();
+    (listed, count)
+}
@@ -0,0 +1,109 @@
+//! Ownership accounting: who uses the space.
+//!
+//! User and group names are resolved from `/etc/passwd` and `/etc/group` only,
+//! so no network lookups (NSS, LDAP) are triggered.
+
+use crate::{DirInfo, Directory, File};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default)]
+/// Bytes and files owned by a user or group
+pub struct OwnerUsage {
+    /// The uid or gid
+    pub id: u32,
+    pub size: u64,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Local user and group names
+pub struct Owners {
+    pub users: HashMap<u32, String>,
+    pub groups: HashMap<u32, String>,
+}
+
+impl Owners {
+    /// Load names from `/etc/passwd` and `/etc/group`. Missing files yield no names.
+    pub fn load() -> Owners {
+        Owners {
+            users: parse_id_file(&std::fs::read_to_string("/etc/passwd").unwrap_or_default()),
+            groups: parse_id_file(&std::fs::read_to_string("/etc/group").unwrap_or_default()),
+        }
+    }
+
+    /// Name of a user, or the uid if it is unknown
+    pub fn user_name(&self, uid: u32) -> String {
+        self.users
+            .get(&uid)
+            .cloned()
+            .unwrap_or_else(|| uid.to_string())
+    }
+
+    /// Name of a group, or the gid if it is unknown
+    pub fn group_name(&self, gid: u32) -> String {
+        self.groups
+            .get(&gid)
+            .cloned()
+            .unwrap_or_else(|| gid.to_string())
+    }
+}
+
+/// Parse `name:password:id:...` lines, as used by both passwd and group files
+pub fn parse_id_file(content: &str) -> HashMap<u32, String> {
+    content
+        .lines()
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|l| {
+            let mut fields = l.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((id, name.to_string()))
+        })
+        .collect()
+}
+
+/// Sum up files by an owner id, largest first
+fn usage_by<'a, I, F>(files: I, id: F) -> Vec<OwnerUsage>
+where
+    I: IntoIterator<Item = &'a File>,
+    F: Fn(&File) -> Option<u32>,
+{
+    let mut usage: HashMap<u32, OwnerUsage> = HashMap::new();
+    for file in files {
+        if let Some(id) = id(file) {
+            let u = usage.entry(id).or_insert(OwnerUsage {
+                id,
+                ..Default::default()
+            });
+            u.size += file.size;
+            u.count += 1;
+        }
+    }
+    let mut usage: Vec<OwnerUsage> = usage.into_values().collect();
+    usage.sort_by(|a, b| b.size.cmp(&a.size).then(a.id.cmp(&b.id)));
+    usage
+}
+
+impl DirInfo {
+    /// Return usage per uid, ordered by size
+    pub fn usage_by_user(&self) -> Vec<OwnerUsage> {
+        usage_by(&self.files, |f| f.uid)
+    }
+
+    /// Return usage per gid, ordered by size
+    pub fn usage_by_group(&self) -> Vec<OwnerUsage> {
+        usage_by(&self.files, |f| f.gid)
+    }
+}
+
+impl Directory {
+    /// Return usage per uid in this directory and all subdirectories, ordered by size
+    pub fn usage_by_user(&self, info: &DirInfo) -> Vec<OwnerUsage> {
+        usage_by(info.subtree_files(self), |f| f.uid)
+    }
+
+    /// Return usage per gid in this directory and all subdirectories, ordered by size
+    pub fn usage_by_group(&self, info: &DirInfo) -> Vec<OwnerUsage> {
+        usage_by(info.subtree_files(self), |f| f.gid)
+    }
+}
@@ -0,0 +1,184 @@
+//! Disk usage metrics in the Prometheus text format, for the node_exporter textfile collector.
+
+use crate::{DirInfo, Directory};
+use anyhow::Result;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+/// Which directories get their own series
+pub struct PrometheusOptions {
+    /// Directories at most this many levels below the root
+    pub max_depth: usize,
+    /// Directories of at least this combined size, at any depth
+    pub min_size: Option<u64>,
+}
+
+impl Default for PrometheusOptions {
+    fn default() -> Self {
+        PrometheusOptions {
+            max_depth: 1,
+            min_size: None,
+        }
+    }
+}
+
+/// Write metrics for the scan of `root`. Every series is labelled with the root.
+pub fn write_prometheus<W: Write>(
+    info: &DirInfo,
+    root: &Path,
+    options: &PrometheusOptions,
+    mut w: W,
+) -> Result<()> {
+    let root_label = format!("root=\"{}\"", label_value(&root.display().to_string()));
+
+    metric(&mut w, "size_bytes", "Combined size of all files")?;
+    writeln!(
+        w,
+        "diskspace_size_bytes{{{}}} {}",
+        root_label, info.combined_size
+    )?;
+    metric(
+        &mut w,
+        "allocated_bytes",
+        "Space allocated on disk for all files",
+    )?;
+    writeln!(
+        w,
+        "diskspace_allocated_bytes{{{}}} {}",
+        root_label, info.combined_allocated_size
+    )?;
+    metric(&mut w, "files", "Number of files")?;
+    writeln!(w, "diskspace_files{{{}}} {}", root_label, info.files.len())?;
+
+    metric(
+        &mut w,
+        "directory_size_bytes",
+        "Combined size of a directory, including subdirectories",
+    )?;
+    if let Some(dir) = info.tree.get(root) {
+        write_dir(info, dir, 0, options, &root_label, &mut w)?;
+    }
+
+    let mut types: Vec<_> = info.filetypes.values().collect();
+    types.sort_by(|a, b| a.ext.cmp(&b.ext));
+    metric(
+        &mut w,
+        "extension_size_bytes",
+        "Combined size of files by extension",
+    )?;
+    for ftype in &types {
+        writeln!(
+            w,
+            "diskspace_extension_size_bytes{{{},ext=\"{}\"}} {}",
+            root_label,
+            label_value(&ftype.ext),
+            ftype.size
+        )?;
+    }
+    metric(&mut w, "extension_files", "Number of files by extension")?;
+    for ftype in &types {
+        writeln!(
+            w,
+            "diskspace_extension_files{{{},ext=\"{}\"}} {}",
+            root_label,
+            label_value(&ftype.ext),
+            ftype.files.len()
+        )?;
+    }
+
+    let duplicates = info.duplicate_report();
+    metric(
+        &mut w,
+        "duplicate_groups",
+        "Number of sets of identical files",
+    )?;
+    writeln!(
+        w,
+        "diskspace_duplicate_groups{{{}}} {}",
+        root_label,
+        duplicates.groups.len()
+    )?;
+    metric(
+        &mut w,
+        "duplicate_wasted_bytes",
+        "Space taken by all but one copy of duplicate files",
+    )?;
+    writeln!(
+        w,
+        "diskspace_duplicate_wasted_bytes{{{}}} {}",
+        root_label, duplicates.reclaimable
+    )?;
+    Ok(())
+}
+
+/// Write metrics to `path`, through a temporary file that is renamed into place,
+/// so that the textfile collector never reads a partial file
+pub fn write_textfile(
+    info: &DirInfo,
+    root: &Path,
+    options: &PrometheusOptions,
+    path: &Path,
+) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.tmp", std::process::id()));
+    let written = fs::File::create(&tmp)
+        .map_err(Into::into)
+        .and_then(|file| {
+            let mut w = BufWriter::new(file);
+            write_prometheus(info, root, options, &mut w)?;
+            w.flush()?;
+            Ok(())
+        })
+        .and_then(|_| fs::rename(&tmp, path).map_err(Into::into));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    written
+}
+
+fn write_dir<W: Write>(
+    info: &DirInfo,
+    dir: &Directory,
+    depth: usize,
+    options: &PrometheusOptions,
+    root_label: &str,
+    w: &mut W,
+) -> Result<()> {
+    let large = options
+        .min_size
+        .map(|s| dir.combined_size >= s)
+        .unwrap_or(false);
+    if depth <= options.max_depth || large {
+        writeln!(
+            w,
+            "diskspace_directory_size_bytes{{{},path=\"{}\",depth=\"{}\"}} {}",
+            root_label,
+            label_value(&dir.path.display().to_string()),
+            depth,
+            dir.combined_size
+        )?;
+    }
+    // Without a size threshold nothing below the maximum depth is reported
+    if depth >= options.max_depth && options.min_size.is_none() {
+        return Ok(());
+    }
+    for subdir in dir.directories.iter().filter_map(|d| info.tree.get(d)) {
+        write_dir(info, subdir, depth + 1, options, root_label, w)?;
+    }
+    Ok(())
+}
+
+fn metric<W: Write>(w: &mut W, name: &str, help: &str) -> Result<()> {
+    writeln!(w, "# HELP diskspace_{} {}", name, help)?;
+    writeln!(w, "# TYPE diskspace_{} gauge", name)?;
+    Ok(())
+}
+
+/// Escape a label value as the text format requires
+fn label_value(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,133 @@
+//! Renderer independent sunburst layout: the tree as rings of arcs around its root.
+
+use crate::treemap::{children, NodeKind};
+use crate::DirInfo;
+use std::f64::consts::TAU;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What an arc stands for
+pub enum ArcKind {
+    Directory,
+    /// All files directly in a directory, like `Directory::files_as_fake_dir`
+    Files,
+    File,
+    /// Entries too small to show on their own, merged. Holds their number.
+    Other(usize),
+}
+
+impl From<NodeKind> for ArcKind {
+    fn from(kind: NodeKind) -> Self {
+        match kind {
+            NodeKind::Directory => ArcKind::Directory,
+            NodeKind::Files => ArcKind::Files,
+            NodeKind::File => ArcKind::File,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A laid out arc. Angles are in radians, radii are relative to the full sunburst, which has a radius of 1.
+pub struct SunburstArc {
+    /// Path of the directory or file. `Files` and `Other` arcs carry the path of their parent.
+    pub path: PathBuf,
+    pub kind: ArcKind,
+    pub size: u64,
+    /// Levels below the root. The root is a full circle at depth 0.
+    pub depth: usize,
+    pub start_angle: f64,
+    pub end_angle: f64,
+    pub inner_radius: f64,
+    pub outer_radius: f64,
+}
+
+/// Lay out the tree below `root` down to `max_depth` levels.
+/// Children spanning less than `min_angle` are merged into an `Other` arc.
+/// Arcs are returned parents first.
+pub fn sunburst(info: &DirInfo, root: &Path, max_depth: usize, min_angle: f64) -> Vec<SunburstArc> {
+    let dir = match info.tree.get(root) {
+        Some(dir) => dir,
+        None => return vec![],
+    };
+    let ring = 1.0 / (max_depth + 1) as f64;
+    let mut arcs = vec![SunburstArc {
+        path: dir.path.clone(),
+        kind: ArcKind::Directory,
+        size: dir.combined_size,
+        depth: 0,
+        start_angle: 0.0,
+        end_angle: TAU,
+        inner_radius: 0.0,
+        outer_radius: ring,
+    }];
+    layout(info, 0, max_depth, min_angle, ring, &mut arcs);
+    arcs
+}
+
+/// Lay out the children of `arcs[parent]`, appending them and their descendants
+fn layout(
+    info: &DirInfo,
+    parent: usize,
+    max_depth: usize,
+    min_angle: f64,
+    ring: f64,
+    arcs: &mut Vec<SunburstArc>,
+) {
+    let p = arcs[parent].clone();
+    let kind = match p.kind {
+        ArcKind::Directory => NodeKind::Directory,
+        ArcKind::Files => NodeKind::Files,
+        _ => return,
+    };
+    if p.depth >= max_depth || p.size == 0 {
+        return;
+    }
+
+    let depth = p.depth + 1;
+    let per_byte = (p.end_angle - p.start_angle) / p.size as f64;
+    let children = children(info, &p.path, kind);
+    // Children are sorted by size, so all slivers are at the end
+    let shown = children
+        .iter()
+        .position(|c| (c.2 as f64 * per_byte) < min_angle)
+        .unwrap_or(children.len());
+    // A single sliver is shown as itself rather than as "other"
+    let shown = if children.len() - shown == 1 {
+        children.len()
+    } else {
+        shown
+    };
+
+    let mut angle = p.start_angle;
+    for (path, kind, size) in &children[..shown] {
+        let end = angle + *size as f64 * per_byte;
+        arcs.push(SunburstArc {
+            path: path.clone(),
+            kind: (*kind).into(),
+            size: *size,
+            depth,
+            start_angle: angle,
+            end_angle: end,
+            inner_radius: depth as f64 * ring,
+            outer_radius: (depth + 1) as f64 * ring,
+        });
+        angle = end;
+        let index = arcs.len() - 1;
+        layout(info, index, max_depth, min_angle, ring, arcs);
+    }
+
+    let slivers = &children[shown..];
+    if !slivers.is_empty() {
+        let size: u64 = slivers.iter().map(|c| c.2).sum();
+        arcs.push(SunburstArc {
+            path: p.path.clone(),
+            kind: ArcKind::Other(slivers.len()),
+            size,
+            depth,
+            start_angle: angle,
+            end_angle: angle + size as f64 * per_byte,
+            inner_radius: depth as f64 * ring,
+            outer_radius: (depth + 1) as f64 * ring,
+        });
+    }
+}
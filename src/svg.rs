@@ -0,0 +1,160 @@
+//! SVG treemap export, based on the layout in [`crate::treemap`].
+
+use crate::category::{Category, CategoryTable};
+use crate::html::escape;
+use crate::treemap::{treemap_with_header, NodeKind, Rect, TreemapNode};
+use crate::{DirInfo, File};
+use anyhow::{anyhow, Result};
+use bytesize::ByteSize;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::Path;
+
+/// Font size of labels, in pixels
+const FONT_SIZE: f64 = 11.0;
+/// Band at the top of each directory for its label, kept free of its children
+const HEADER: f64 = FONT_SIZE + 4.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How files are coloured
+pub enum ColorBy {
+    Extension,
+    Category,
+}
+
+#[derive(Debug, Clone)]
+/// Size and content of an SVG treemap
+pub struct SvgOptions {
+    pub width: f64,
+    pub height: f64,
+    /// Directory levels to lay out
+    pub max_depth: usize,
+    pub color_by: ColorBy,
+    /// Categories used when colouring by category
+    pub categories: CategoryTable,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions {
+            width: 1280.0,
+            height: 800.0,
+            max_depth: 12,
+            color_by: ColorBy::Extension,
+            categories: CategoryTable::default(),
+        }
+    }
+}
+
+/// Write the tree below `root` as an SVG treemap
+pub fn write_svg_treemap<W: Write>(
+    info: &DirInfo,
+    root: &Path,
+    options: &SvgOptions,
+    mut w: W,
+) -> Result<()> {
+    let rect = Rect::new(0.0, 0.0, options.width, options.height);
+    let map = treemap_with_header(info, root, rect, options.max_depth, HEADER)
+        .ok_or_else(|| anyhow!("{} is not part of the scan", root.display()))?;
+    let nodes = map.flatten();
+
+    // Files are looked up by path to colour them
+    let files: HashMap<&Path, &File> = nodes
+        .iter()
+        .filter(|n| n.kind == NodeKind::Files)
+        .filter_map(|n| info.tree.get(&n.path))
+        .flat_map(|d| d.files.iter())
+        .map(|f| (f.path.as_path(), f))
+        .collect();
+
+    writeln!(
+        w,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\" font-size=\"{f}\">",
+        w = options.width,
+        h = options.height,
+        f = FONT_SIZE
+    )?;
+    for node in nodes {
+        // The files node only groups files, which are drawn themselves
+        if node.kind == NodeKind::Files && !node.children.is_empty() {
+            continue;
+        }
+        let fill = match node.kind {
+            NodeKind::File => files
+                .get(node.path.as_path())
+                .map(|f| color(f, options))
+                .unwrap_or_else(|| "#cccccc".to_string()),
+            _ if node.children.is_empty() => "#bbbbbb".to_string(),
+            _ => "#eeeeee".to_string(),
+        };
+        write_node(node, &fill, &mut w)?;
+    }
+    writeln!(w, "</svg>")?;
+    Ok(())
+}
+
+fn write_node<W: Write>(node: &TreemapNode, fill: &str, w: &mut W) -> Result<()> {
+    let r = node.rect;
+    let name = match node.kind {
+        NodeKind::Files => "Files".to_string(),
+        _ => node
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| node.path.display().to_string()),
+    };
+    writeln!(
+        w,
+        "<g><title>{} ({})</title><rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" fill=\"{}\" stroke=\"#ffffff\" stroke-width=\"0.5\"/>",
+        escape(&node.path.display().to_string()),
+        ByteSize(node.size),
+        r.x,
+        r.y,
+        r.w,
+        r.h,
+        fill
+    )?;
+    // Only label rectangles the name fits into, assuming an average glyph width of 0.6 em
+    let label_width = name.chars().count() as f64 * FONT_SIZE * 0.6 + 4.0;
+    if r.w > label_width && r.h > HEADER {
+        writeln!(
+            w,
+            "<text x=\"{:.2}\" y=\"{:.2}\">{}</text>",
+            r.x + 2.0,
+            r.y + FONT_SIZE + 1.0,
+            escape(&name)
+        )?;
+    }
+    writeln!(w, "</g>")?;
+    Ok(())
+}
+
+/// Colour of a file: fixed per category, or derived from the extension
+fn color(file: &File, options: &SvgOptions) -> String {
+    let hue = match options.color_by {
+        ColorBy::Category => match options.categories.category_of(file) {
+            Category::Video => 0,
+            Category::Image => 35,
+            Category::Audio => 290,
+            Category::Archive => 200,
+            Category::Document => 120,
+            Category::Code => 170,
+            Category::BuildArtifact => 20,
+            Category::Custom(name) => hue_of(&name),
+            Category::Other => return "#999999".to_string(),
+        },
+        ColorBy::Extension => match file.type_ext() {
+            Some(ext) => hue_of(ext),
+            None => return "#999999".to_string(),
+        },
+    };
+    format!("hsl({}, 60%, 60%)", hue)
+}
+
+/// A stable hue for a name
+fn hue_of(name: &str) -> u64 {
+    let mut s: twox_hash::Xxh3Hash64 = Default::default();
+    name.hash(&mut s);
+    s.finish() % 360
+}
@@ -1,5 +1,4 @@
 use super::*;
-use env_logger;
 use log::*;
 use std::process::Command;
 
@@ -243,7 +242,9 @@ fn zip() {
         .output()
         .unwrap();
 
-    let i = scan_archive("archive.zip");
+    let i = scan_archive("archive.zip").unwrap();
+    assert!(scan_archive("Cargo.toml").is_err());
+    assert!(scan_archive("nothere.zip").is_err());
     info!("=== ZIP Files By Size");
 
     for d in &i.files_by_size {
@@ -264,6 +265,12 @@ fn zip() {
     info!("=== ZIP Duplicates");
     info!("{:#?}", i.duplicates);
 
+    // Entries are relative to the archive, which is the root
+    if !i.files.is_empty() {
+        assert_eq!(i.root, PathBuf::new());
+        assert_eq!(i.tree[&i.root].combined_size, i.combined_size);
+    }
+
     Command::new("rm")
         .arg("-rf")
         .arg("treetest")
@@ -275,3 +282,1680 @@ fn zip() {
         .output()
         .unwrap();
 }
+
+#[test]
+fn age() {
+    std::env::set_var("RUST_LOG", "debug");
+    let _ = env_logger::try_init();
+
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("agetest/old/deeper")
+        .output()
+        .unwrap();
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("agetest/new")
+        .output()
+        .unwrap();
+
+    for (file, size) in [
+        ("agetest/old/a.txt", "1000"),
+        ("agetest/old/deeper/b.txt", "2000"),
+        ("agetest/new/c.txt", "4000"),
+    ] {
+        Command::new("truncate")
+            .arg("-s")
+            .arg(size)
+            .arg(file)
+            .output()
+            .unwrap();
+    }
+    Command::new("touch")
+        .arg("-d")
+        .arg("3 years ago")
+        .arg("agetest/old/a.txt")
+        .arg("agetest/old/deeper/b.txt")
+        .output()
+        .unwrap();
+
+    let i = scan("agetest");
+    let buckets = age::AgeBuckets::default();
+
+    let report = i.age_report(&buckets);
+    info!("{:#?}", report);
+    assert_eq!(report.buckets.len(), 5);
+    assert_eq!(report.buckets[0].size, 4000);
+    assert_eq!(report.buckets[4].size, 3000);
+    assert_eq!(report.buckets[4].count, 2);
+
+    let old = &i.tree[Path::new("agetest/old")];
+    assert_eq!(old.age_report(&i, &buckets).buckets[4].size, 3000);
+    assert_eq!(i.filetypes["txt"].age_report(&buckets).size(), 7000);
+
+    let year = std::time::Duration::from_secs(60 * 60 * 24 * 365);
+    let stale = i.dirs_by_stale_size(year, buckets.reference);
+    info!("{:#?}", stale);
+    assert_eq!(stale[0].path, Path::new("agetest"));
+    assert_eq!(stale[0].stale_size, 3000);
+    assert_eq!(stale[1].path, Path::new("agetest/old"));
+    assert!(!stale.iter().any(|d| d.path == Path::new("agetest/new")));
+    // Seen from three years ago, nothing was a year old yet
+    assert!(i
+        .dirs_by_stale_size(year, buckets.reference - year * 3)
+        .is_empty());
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("agetest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn mountinfo() {
+    let m = mounts::Mount::parse(
+        "36 35 98:0 /mnt1 /mnt/my\\040disk rw,noatime master:1 - ext3 /dev/root rw,errors=continue",
+    )
+    .unwrap();
+    assert_eq!(m.mount_point, Path::new("/mnt/my disk"));
+    assert_eq!(m.fs_type, "ext3");
+    assert_eq!(m.source, "/dev/root");
+    assert_eq!(m.atime_mode(), mounts::AtimeMode::Noatime);
+
+    let m = mounts::Mount::parse("22 1 0:21 / /proc rw,relatime shared:12 - proc proc rw").unwrap();
+    assert_eq!(m.atime_mode(), mounts::AtimeMode::Relatime);
+    assert!(!m.atime_mode().is_reliable());
+
+    let i = scan("src");
+    info!("atime: {:?}", i.atime_mode);
+    assert!(i.files.iter().all(|f| f.accessed.is_some()));
+}
+
+#[test]
+fn owners() {
+    let users = owners::parse_id_file("root:x:0:0:root:/root:/bin/bash\n# comment\nkaputnik:x:1000:1000::/home/kaputnik:/bin/sh\n");
+    assert_eq!(users[&0], "root");
+    assert_eq!(users[&1000], "kaputnik");
+
+    let i = scan("src");
+    let by_user = i.usage_by_user();
+    info!("{:#?}", by_user);
+    assert_eq!(by_user.iter().map(|u| u.size).sum::<u64>(), i.combined_size);
+
+    let owners = owners::Owners::load();
+    for u in &by_user {
+        info!("{}: {}", owners.user_name(u.id), ByteSize(u.size));
+    }
+    assert_eq!(
+        i.tree[Path::new("src")].usage_by_group(&i).len(),
+        i.usage_by_group().len()
+    );
+}
+
+#[test]
+fn sniff() {
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("snifftest")
+        .output()
+        .unwrap();
+    Command::new("cp")
+        .arg("/bin/ls")
+        .arg("snifftest/binary")
+        .output()
+        .unwrap();
+    std::fs::write("snifftest/database", b"SQLite format 3\0rest").unwrap();
+    std::fs::write("snifftest/notes", b"just text").unwrap();
+    std::fs::write("snifftest/photo.txt", b"\x89PNG\r\n\x1a\nrest").unwrap();
+    // Formats built on zip, scripts and images named by another extension of their kind
+    std::fs::write("snifftest/report.docx", b"PK\x03\x04rest").unwrap();
+    std::fs::write("snifftest/app.jar", b"PK\x03\x04rest").unwrap();
+    std::fs::write("snifftest/run.sh", b"#!/bin/sh\n").unwrap();
+    std::fs::write("snifftest/scan.jpeg", b"\xff\xd8\xff\xe0rest").unwrap();
+
+    let options = ScanOptions {
+        sniff_types: true,
+        ..Default::default()
+    };
+    let i = scan_with_options("snifftest", &options, |_| {}, u128::MAX);
+    info!("{:#?}", i.filetypes.keys());
+    assert_eq!(i.filetypes["elf"].files.len(), 1);
+    assert_eq!(
+        i.filetypes["sqlite"].files[0].mime.as_deref(),
+        Some("application/vnd.sqlite3")
+    );
+    // A mislabelled file is counted as what it is, but keeps its extension
+    let photo = &i.filetypes["png"].files[0];
+    assert_eq!(photo.ext.as_deref(), Some("txt"));
+    assert_eq!(photo.detected_ext.as_deref(), Some("png"));
+    for ext in &["docx", "jar", "sh", "jpeg"] {
+        assert_eq!(i.filetypes[*ext].files.len(), 1);
+        assert_eq!(i.filetypes[*ext].files[0].detected_ext, None);
+    }
+    assert!(!i.filetypes.contains_key("zip"));
+    assert_eq!(
+        i.filetypes["docx"].files[0].mime.as_deref(),
+        Some("application/zip")
+    );
+    assert_eq!(
+        category::CategoryTable::default().category_of(&i.filetypes["docx"].files[0]),
+        category::Category::Document
+    );
+    assert_eq!(i.filetypes.len(), 7);
+
+    let i = scan("snifftest");
+    assert_eq!(i.filetypes.len(), 5);
+    assert_eq!(i.filetypes["txt"].files.len(), 1);
+
+    let brand = |brand: &[u8]| {
+        let mut header = b"\0\0\0\x18ftyp".to_vec();
+        header.extend_from_slice(brand);
+        magic::sniff(&header).map(|m| m.ext)
+    };
+    assert_eq!(brand(b"heic"), Some("heic"));
+    assert_eq!(brand(b"M4A "), Some("m4a"));
+    assert_eq!(brand(b"qt  "), Some("mov"));
+    assert_eq!(brand(b"isom"), Some("mp4"));
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("snifftest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn categories() {
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("categorytest/photos")
+        .output()
+        .unwrap();
+    for (file, size) in [
+        ("categorytest/photos/a.JPG", "3000"),
+        ("categorytest/photos/b.png", "1000"),
+        ("categorytest/movie.mkv", "5000"),
+        ("categorytest/main.rs", "100"),
+        ("categorytest/thing.custom", "10"),
+    ] {
+        Command::new("truncate")
+            .arg("-s")
+            .arg(size)
+            .arg(file)
+            .output()
+            .unwrap();
+    }
+
+    let i = scan("categorytest");
+    let categories = i.categories_by_size();
+    info!("{:#?}", categories);
+    assert_eq!(categories[0].category, category::Category::Video);
+    assert_eq!(categories[1].category, category::Category::Image);
+    assert_eq!(categories[1].size, 4000);
+    assert_eq!(
+        categories.last().unwrap().category,
+        category::Category::Other
+    );
+
+    let mut table = category::CategoryTable::default();
+    table.insert_ext("custom", category::Category::Custom("Mine".into()));
+    let photos = &i.tree[Path::new("categorytest/photos")];
+    assert_eq!(photos.categories_by_size(&i, &table).len(), 1);
+    assert!(i
+        .categories_by_size_with(&table)
+        .iter()
+        .any(|c| c.category.to_string() == "Mine"));
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("categorytest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn ncdu_roundtrip() {
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("ncdutest/a/b")
+        .output()
+        .unwrap();
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("ncdutest/empty")
+        .output()
+        .unwrap();
+    for (file, size) in [
+        ("ncdutest/top.txt", "100"),
+        ("ncdutest/a/one.bin", "2000"),
+        ("ncdutest/a/b/two \"quoted\".bin", "30000"),
+    ] {
+        Command::new("truncate")
+            .arg("-s")
+            .arg(size)
+            .arg(file)
+            .output()
+            .unwrap();
+    }
+
+    let i = scan("ncdutest");
+    let mut dump = vec![];
+    ncdu::write_ncdu(&i, Path::new("ncdutest"), &mut dump).unwrap();
+    info!("{}", String::from_utf8_lossy(&dump));
+
+    let mut imported = ncdu::read_ncdu(dump.as_slice()).unwrap();
+    let root = Path::new("ncdutest").canonicalize().unwrap();
+    assert_eq!(imported.combined_size, 32100);
+    assert_eq!(imported.files.len(), 3);
+    assert_eq!(imported.tree[&root].combined_size, 32100);
+    assert_eq!(imported.tree[&root.join("a")].combined_size, 32000);
+    assert_eq!(imported.tree[&root].directories.len(), 2);
+    assert_eq!(imported.types_by_size[0].ext, "bin");
+    assert_eq!(imported.files_by_size[0].size, 30000);
+    assert!(imported.duplicates.is_empty());
+    // Imported files have no hash, so changes do not make them duplicates of each other
+    imported.remove_path(&root.join("top.txt"));
+    assert_eq!(imported.files.len(), 2);
+    assert!(imported.duplicates.is_empty());
+    imported
+        .move_path(&root.join("a"), &root.join("empty/a"))
+        .unwrap();
+    assert!(imported.duplicates.is_empty());
+    assert_eq!(imported.tree[&root.join("empty")].combined_size, 32000);
+    assert!(imported
+        .move_path(&root.join("nothere"), &root.join("b"))
+        .is_err());
+
+    let dump = r#"[1,0,{"progname":"ncdu","progver":"1.15","timestamp":1600000000},
+        [{"name":"/srv","asize":4096,"dsize":4096},
+         {"name":"backup.tar","asize":1000,"dsize":4096},
+         {"name":"skipped","excluded":"pattern"},
+         [{"name":"www"},{"name":"index.html","asize":10,"dsize":4096,"mtime":1500000000}]]]"#;
+    let imported = ncdu::read_ncdu(dump.as_bytes()).unwrap();
+    assert_eq!(imported.combined_size, 1010);
+    assert_eq!(imported.combined_allocated_size, 8192);
+    assert_eq!(imported.tree[Path::new("/srv/www")].combined_size, 10);
+    assert!(ncdu::read_ncdu(&b"[2,0,{}]"[..]).is_err());
+
+    // Names that would leave their directory are refused
+    for name in ["../etc", "/etc/passwd", "a/b", "..", "."] {
+        let file = format!(r#"[1,0,{{}},[{{"name":"/srv"}},{{"name":{:?}}}]]"#, name);
+        assert!(ncdu::read_ncdu(file.as_bytes()).is_err(), "{}", name);
+        let dir = format!(r#"[1,0,{{}},[{{"name":"/srv"}},[{{"name":{:?}}}]]]"#, name);
+        assert!(ncdu::read_ncdu(dir.as_bytes()).is_err(), "{}", name);
+    }
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("ncdutest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn treemap() {
+    use treemap::{squarify, NodeKind, Rect};
+
+    let rects = squarify(&[6, 6, 4, 3, 2, 2, 1], Rect::new(0.0, 0.0, 6.0, 4.0));
+    for (rect, size) in rects.iter().zip([6, 6, 4, 3, 2, 2, 1]) {
+        assert!((rect.area() - size as f64).abs() < 1e-9);
+        assert!(rect.x >= 0.0 && rect.x + rect.w <= 6.0 + 1e-9);
+        assert!(rect.y >= 0.0 && rect.y + rect.h <= 4.0 + 1e-9);
+    }
+
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("treemaptest/a/b")
+        .output()
+        .unwrap();
+    for (file, size) in [
+        ("treemaptest/top.txt", "1000"),
+        ("treemaptest/a/one.bin", "2000"),
+        ("treemaptest/a/b/two.bin", "7000"),
+    ] {
+        Command::new("truncate")
+            .arg("-s")
+            .arg(size)
+            .arg(file)
+            .output()
+            .unwrap();
+    }
+
+    let i = scan("treemaptest");
+    let rect = Rect::new(0.0, 0.0, 100.0, 100.0);
+    let map = treemap::treemap(&i, Path::new("treemaptest"), rect, 10).unwrap();
+    let nodes = map.flatten();
+    info!(
+        "{:#?}",
+        nodes
+            .iter()
+            .map(|n| (&n.path, n.kind, n.rect))
+            .collect::<Vec<_>>()
+    );
+    let file = nodes
+        .iter()
+        .find(|n| n.path == Path::new("treemaptest/a/b/two.bin"))
+        .unwrap();
+    assert_eq!(file.kind, NodeKind::File);
+    assert!((file.rect.area() - 7000.0).abs() < 1e-6);
+    assert_eq!(
+        nodes.iter().filter(|n| n.kind == NodeKind::Files).count(),
+        3
+    );
+
+    let headed = treemap::treemap_with_header(&i, Path::new("treemaptest"), rect, 10, 5.0).unwrap();
+    assert!(headed.children.iter().all(|c| c.rect.y >= 5.0));
+
+    let shallow = treemap::treemap(&i, Path::new("treemaptest"), rect, 1).unwrap();
+    assert_eq!(shallow.children.len(), 2);
+    assert!(shallow.children.iter().all(|c| c.children.is_empty()));
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("treemaptest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn sunburst() {
+    use sunburst::ArcKind;
+
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("sunbursttest/big")
+        .output()
+        .unwrap();
+    Command::new("truncate")
+        .arg("-s")
+        .arg("100000")
+        .arg("sunbursttest/big/large.bin")
+        .output()
+        .unwrap();
+    for n in 0..5 {
+        Command::new("truncate")
+            .arg("-s")
+            .arg("10")
+            .arg(format!("sunbursttest/tiny{}.txt", n))
+            .output()
+            .unwrap();
+    }
+
+    let i = scan("sunbursttest");
+    let arcs = sunburst::sunburst(&i, Path::new("sunbursttest"), 3, 0.01);
+    info!("{:#?}", arcs);
+    assert_eq!(arcs[0].depth, 0);
+    assert!((arcs[0].end_angle - std::f64::consts::TAU).abs() < 1e-9);
+
+    let other = arcs
+        .iter()
+        .find(|a| matches!(a.kind, ArcKind::Other(_)))
+        .unwrap();
+    assert_eq!(other.kind, ArcKind::Other(5));
+    assert_eq!(other.size, 50);
+    assert_eq!(other.depth, 2);
+
+    // Every ring adds up to the full circle
+    let ring1: f64 = arcs
+        .iter()
+        .filter(|a| a.depth == 1)
+        .map(|a| a.end_angle - a.start_angle)
+        .sum();
+    assert!((ring1 - std::f64::consts::TAU).abs() < 1e-9);
+    assert!(arcs.iter().all(|a| a.outer_radius <= 1.0 + 1e-9));
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("sunbursttest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn html_report() {
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("htmltest/a")
+        .output()
+        .unwrap();
+    std::fs::write("htmltest/a/<script>.txt", b"duplicate content").unwrap();
+    std::fs::write("htmltest/copy.txt", b"duplicate content").unwrap();
+
+    let i = scan("htmltest");
+    let mut html = vec![];
+    html::write_html_report(
+        &i,
+        Path::new("htmltest"),
+        &html::HtmlOptions::default(),
+        &mut html,
+    )
+    .unwrap();
+    let html = String::from_utf8(html).unwrap();
+    assert!(html.contains("&lt;script&gt;.txt"));
+    assert!(!html.contains("<script>"));
+    assert!(html.contains("17 B reclaimable in 1 groups"));
+    assert!(!html.contains("http"));
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("htmltest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn svg_treemap() {
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("svgtest/a")
+        .output()
+        .unwrap();
+    std::fs::write("svgtest/a/big&small.png", vec![0; 4000]).unwrap();
+    std::fs::write("svgtest/notes.txt", vec![0; 1000]).unwrap();
+    std::fs::write("svgtest/blob.unknown", vec![0; 1000]).unwrap();
+
+    let i = scan("svgtest");
+    let mut svg = vec![];
+    svg::write_svg_treemap(
+        &i,
+        Path::new("svgtest"),
+        &svg::SvgOptions::default(),
+        &mut svg,
+    )
+    .unwrap();
+    let svg = String::from_utf8(svg).unwrap();
+    info!("{}", svg);
+    assert!(svg.starts_with("<svg"));
+    assert!(svg.contains("<title>svgtest/a/big&amp;small.png (4.0 KB)</title>"));
+    assert!(svg.contains(">notes.txt</text>"));
+    assert_eq!(svg.matches("<rect").count(), 5);
+
+    // Directory labels sit in a band their children leave free
+    let y_of = |title: &str| -> f64 {
+        let line = svg
+            .lines()
+            .find(|l| l.contains(&format!("<title>{} (", title)))
+            .unwrap();
+        let y = line.split(" y=\"").nth(1).unwrap();
+        y.split('"').next().unwrap().parse().unwrap()
+    };
+    assert!(svg.contains(">a</text>"));
+    let dir_y = y_of("svgtest/a");
+    let file_y = y_of("svgtest/a/big&amp;small.png");
+    assert!(file_y >= dir_y + 15.0);
+
+    let options = svg::SvgOptions {
+        color_by: svg::ColorBy::Category,
+        ..Default::default()
+    };
+    let mut by_category = vec![];
+    svg::write_svg_treemap(&i, Path::new("svgtest"), &options, &mut by_category).unwrap();
+    let by_category = String::from_utf8(by_category).unwrap();
+    let fill = |title: &str| {
+        let line = by_category
+            .lines()
+            .find(|l| l.contains(&format!("<title>{} (", title)))
+            .unwrap();
+        line.split("fill=\"")
+            .nth(1)
+            .unwrap()
+            .split('"')
+            .next()
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(fill("svgtest/a/big&amp;small.png"), "hsl(35, 60%, 60%)");
+    assert_eq!(fill("svgtest/notes.txt"), "hsl(120, 60%, 60%)");
+    assert_eq!(fill("svgtest/blob.unknown"), "#999999");
+    assert!(svg::write_svg_treemap(&i, Path::new("nothere"), &options, vec![]).is_err());
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("svgtest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn export() {
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("exporttest/a")
+        .output()
+        .unwrap();
+    std::fs::write("exporttest/a/one,two.txt", b"12345").unwrap();
+    std::fs::write("exporttest/b", b"123").unwrap();
+
+    let i = scan("exporttest");
+    let root = Path::new("exporttest");
+    let mut csv = vec![];
+    export::write_files(&i, root, export::ExportFormat::Csv, &mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let mut lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.remove(0), "path,size,allocated,ext,mtime,hash");
+    lines.sort_unstable();
+    assert!(lines[0].starts_with("\"exporttest/a/one,two.txt\",5,"));
+    assert!(lines[1].starts_with("exporttest/b,3,"));
+
+    let mut jsonl = vec![];
+    export::write_dirs(&i, root, export::ExportFormat::JsonLines, &mut jsonl).unwrap();
+    let dirs: Vec<serde_json::Value> = String::from_utf8(jsonl)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(dirs.len(), 2);
+    assert_eq!(dirs[0]["path"], "exporttest");
+    assert_eq!(dirs[0]["combined_size"], 8);
+    assert_eq!(dirs[0]["files"], 1);
+    assert_eq!(dirs[1]["depth"], 1);
+    assert_eq!(dirs[1]["size"], 5);
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("exporttest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn folded() {
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("foldedtest/a/b")
+        .output()
+        .unwrap();
+    std::fs::write("foldedtest/a/b/x;y.txt", b"12345").unwrap();
+    std::fs::write("foldedtest/a/c", b"123").unwrap();
+    std::fs::write("foldedtest/empty", b"").unwrap();
+
+    let i = scan("foldedtest");
+    let root = Path::new("foldedtest");
+    let lines = |max_depth| {
+        let mut out = vec![];
+        folded::write_folded(&i, root, max_depth, &mut out).unwrap();
+        let mut lines: Vec<String> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        lines.sort_unstable();
+        lines
+    };
+    assert_eq!(
+        lines(None),
+        vec!["foldedtest;a;b;x_y.txt 5", "foldedtest;a;c 3"]
+    );
+    assert_eq!(lines(Some(1)), vec!["foldedtest;a 8"]);
+    assert_eq!(lines(Some(2)), vec!["foldedtest;a;b 5", "foldedtest;a;c 3"]);
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("foldedtest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn prometheus_metrics() {
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("prometheustest/a/b")
+        .output()
+        .unwrap();
+    std::fs::write("prometheustest/a/b/one.txt", b"same").unwrap();
+    std::fs::write("prometheustest/a/two.txt", b"same").unwrap();
+    std::fs::write("prometheustest/a/big.bin", vec![0; 100]).unwrap();
+
+    let i = scan("prometheustest");
+    let root = Path::new("prometheustest");
+    let metrics = |options: &prometheus::PrometheusOptions| {
+        let mut out = vec![];
+        prometheus::write_prometheus(&i, root, options, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    let text = metrics(&Default::default());
+    info!("{}", text);
+    assert!(text.contains("diskspace_size_bytes{root=\"prometheustest\"} 108\n"));
+    assert!(text.contains("diskspace_files{root=\"prometheustest\"} 3\n"));
+    assert!(text.contains(
+        "diskspace_directory_size_bytes{root=\"prometheustest\",path=\"prometheustest/a\",depth=\"1\"} 108\n"
+    ));
+    assert!(!text.contains("path=\"prometheustest/a/b\""));
+    assert!(
+        text.contains("diskspace_extension_size_bytes{root=\"prometheustest\",ext=\"txt\"} 8\n")
+    );
+    assert!(text.contains("diskspace_duplicate_wasted_bytes{root=\"prometheustest\"} 4\n"));
+    assert_eq!(text.matches("# TYPE").count(), 8);
+
+    // Small directories only show up within the depth
+    let text = metrics(&prometheus::PrometheusOptions {
+        max_depth: 0,
+        min_size: Some(4),
+    });
+    assert!(text.contains("path=\"prometheustest/a/b\",depth=\"2\"} 4\n"));
+
+    prometheus::write_textfile(
+        &i,
+        root,
+        &Default::default(),
+        Path::new("prometheustest/metrics.prom"),
+    )
+    .unwrap();
+    assert_eq!(
+        std::fs::read_to_string("prometheustest/metrics.prom").unwrap(),
+        metrics(&Default::default())
+    );
+
+    // A file can not replace a directory, and the temporary file is cleaned up
+    std::fs::create_dir("prometheustest/taken").unwrap();
+    assert!(prometheus::write_textfile(
+        &i,
+        root,
+        &Default::default(),
+        Path::new("prometheustest/taken"),
+    )
+    .is_err());
+    assert!(!std::fs::read_dir("prometheustest").unwrap().any(|e| e
+        .unwrap()
+        .file_name()
+        .to_string_lossy()
+        .ends_with(".tmp")));
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("prometheustest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn duplicate_report() {
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("duplicatetest/a")
+        .output()
+        .unwrap();
+    std::fs::write("duplicatetest/a/one", b"small").unwrap();
+    std::fs::write("duplicatetest/two", b"small").unwrap();
+    std::fs::write("duplicatetest/three", b"small").unwrap();
+    std::fs::write("duplicatetest/a/big", vec![1; 100]).unwrap();
+    std::fs::write("duplicatetest/big", vec![1; 100]).unwrap();
+    std::fs::write("duplicatetest/unique", b"unique").unwrap();
+    std::fs::write("duplicatetest/empty", b"").unwrap();
+    std::fs::write("duplicatetest/a/empty", b"").unwrap();
+
+    let i = scan("duplicatetest");
+    let report = i.duplicate_report();
+    info!("{:#?}", report);
+    assert_eq!(report.groups.len(), 2);
+    assert_eq!(report.groups[0].wasted, 100);
+    assert_eq!(
+        report.groups[0].paths,
+        vec![
+            PathBuf::from("duplicatetest/a/big"),
+            PathBuf::from("duplicatetest/big")
+        ]
+    );
+    assert_eq!(report.groups[1].size, 5);
+    assert_eq!(report.groups[1].count, 3);
+    assert_eq!(report.groups[1].wasted, 10);
+    assert_eq!(report.reclaimable, 110);
+
+    let with_empty = i.duplicate_report_with(true);
+    assert_eq!(with_empty.groups.len(), 3);
+    assert_eq!(with_empty.groups[2].size, 0);
+    assert_eq!(with_empty.reclaimable, 110);
+
+    // Hard links to one file take its space once
+    #[cfg(unix)]
+    {
+        std::fs::hard_link("duplicatetest/big", "duplicatetest/a/link").unwrap();
+        std::fs::remove_file("duplicatetest/a/big").unwrap();
+        std::fs::hard_link("duplicatetest/big", "duplicatetest/a/big").unwrap();
+        let report = scan("duplicatetest").duplicate_report();
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].size, 5);
+        assert_eq!(report.reclaimable, 10);
+
+        std::fs::write("duplicatetest/copy", vec![1; 100]).unwrap();
+        let report = scan("duplicatetest").duplicate_report();
+        assert_eq!(report.groups[0].paths.len(), 4);
+        assert_eq!(report.groups[0].count, 2);
+        assert_eq!(report.groups[0].wasted, 100);
+    }
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("duplicatetest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn duplicate_directories() {
+    for dir in &[
+        "dupedirtest/project/src",
+        "dupedirtest/copy/src",
+        "dupedirtest/other/src",
+        "dupedirtest/empty1",
+        "dupedirtest/empty2",
+    ] {
+        Command::new("mkdir").arg("-p").arg(dir).output().unwrap();
+    }
+    for dir in &["project", "copy"] {
+        std::fs::write(format!("dupedirtest/{}/README", dir), b"readme").unwrap();
+        std::fs::write(format!("dupedirtest/{}/src/main.rs", dir), b"fn main() {}").unwrap();
+    }
+    // Same files, but one of them is named differently
+    std::fs::write("dupedirtest/other/README.md", b"readme").unwrap();
+    std::fs::write("dupedirtest/other/src/main.rs", b"fn main() {}").unwrap();
+
+    let i = scan("dupedirtest");
+    let report = i.duplicate_directories();
+    info!("{:#?}", report);
+    assert_eq!(report.groups.len(), 2);
+    assert_eq!(
+        report.groups[0].paths,
+        vec![
+            PathBuf::from("dupedirtest/copy"),
+            PathBuf::from("dupedirtest/project")
+        ]
+    );
+    assert_eq!(report.groups[0].wasted, 18);
+    // The src directories are covered by their parents, except for the one in other,
+    // so only that one is wasted, next to a copy that the larger group keeps
+    assert_eq!(
+        report.groups[1].paths,
+        vec![
+            PathBuf::from("dupedirtest/copy/src"),
+            PathBuf::from("dupedirtest/other/src")
+        ]
+    );
+    assert_eq!(report.groups[1].size, 12);
+    assert_eq!(report.groups[1].wasted, 12);
+    assert_eq!(report.reclaimable, 30);
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("dupedirtest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn overlapping_directories() {
+    for dir in &[
+        "overlaptest/photos",
+        "overlaptest/backup",
+        "overlaptest/misc",
+    ] {
+        Command::new("mkdir").arg("-p").arg(dir).output().unwrap();
+    }
+    for (i, size) in [100, 200, 300].iter().enumerate() {
+        let content = vec![i as u8; *size];
+        std::fs::write(format!("overlaptest/photos/{}.jpg", i), &content).unwrap();
+        std::fs::write(format!("overlaptest/backup/copy{}.jpg", i), &content).unwrap();
+    }
+    std::fs::write("overlaptest/photos/new.jpg", vec![7; 50]).unwrap();
+    std::fs::write("overlaptest/backup/old.jpg", vec![8; 60]).unwrap();
+    std::fs::write("overlaptest/misc/0.jpg", vec![0; 100]).unwrap();
+    std::fs::write("overlaptest/misc/empty", b"").unwrap();
+    std::fs::write("overlaptest/photos/empty", b"").unwrap();
+
+    let i = scan("overlaptest");
+    let report = i.overlapping_directories();
+    info!("{:#?}", report);
+    assert!(report.widespread.is_empty());
+    let overlaps = report.overlaps;
+    assert_eq!(overlaps.len(), 3);
+    let first = &overlaps[0];
+    assert_eq!(first.first, PathBuf::from("overlaptest/backup"));
+    assert_eq!(first.second, PathBuf::from("overlaptest/photos"));
+    assert_eq!(first.shared_size, 600);
+    assert_eq!(first.shared_count, 3);
+    assert_eq!(
+        first.only_first,
+        vec![PathBuf::from("overlaptest/backup/old.jpg")]
+    );
+    assert_eq!(
+        first.only_second,
+        vec![
+            PathBuf::from("overlaptest/photos/empty"),
+            PathBuf::from("overlaptest/photos/new.jpg")
+        ]
+    );
+    assert_eq!(first.only_second_count, 2);
+    assert!(overlaps[1..].iter().all(|o| o.shared_size == 100));
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("overlaptest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn overlapping_nested_directories() {
+    for dir in &[
+        "overlapnesttest/lib/2020/01",
+        "overlapnesttest/lib/2021",
+        "overlapnesttest/copy/2020/01",
+        "overlapnesttest/copy/2021",
+    ] {
+        Command::new("mkdir").arg("-p").arg(dir).output().unwrap();
+    }
+    for (i, dir) in ["2020/01", "2021"].iter().enumerate() {
+        let content = vec![i as u8; 100];
+        std::fs::write(format!("overlapnesttest/lib/{}/a.jpg", dir), &content).unwrap();
+        std::fs::write(format!("overlapnesttest/copy/{}/a.jpg", dir), &content).unwrap();
+    }
+    // A copy below a directory twice only counts once
+    std::fs::write("overlapnesttest/copy/2021/b.jpg", vec![1; 100]).unwrap();
+    std::fs::write("overlapnesttest/lib/2021/new.jpg", vec![9; 10]).unwrap();
+    // Files only one side has are listed up to a limit, and counted
+    for i in 0..overlap::MAX_LISTED_FILES + 5 {
+        std::fs::write(format!("overlapnesttest/copy/2020/only{:02}", i), [i as u8]).unwrap();
+    }
+
+    // Boilerplate found everywhere is reported instead of pairing up every directory
+    for i in 0..=overlap::MAX_DIRECTORIES {
+        let dir = format!("overlapnesttest/misc/{}", i);
+        Command::new("mkdir").arg("-p").arg(&dir).output().unwrap();
+        std::fs::write(format!("{}/LICENSE", dir), b"license").unwrap();
+    }
+
+    let report = scan("overlapnesttest").overlapping_directories();
+    assert_eq!(report.widespread.len(), 1);
+    assert_eq!(report.widespread[0].count, overlap::MAX_DIRECTORIES + 1);
+    let first = &report.overlaps[0];
+    assert_eq!(first.first, PathBuf::from("overlapnesttest/copy"));
+    assert_eq!(first.second, PathBuf::from("overlapnesttest/lib"));
+    assert_eq!(first.shared_size, 200);
+    assert_eq!(first.shared_count, 2);
+    assert_eq!(first.only_first.len(), overlap::MAX_LISTED_FILES);
+    assert_eq!(first.only_first_count, overlap::MAX_LISTED_FILES + 5);
+    assert_eq!(
+        first.only_first[0],
+        PathBuf::from("overlapnesttest/copy/2020/only00")
+    );
+    assert_eq!(
+        first.only_second,
+        vec![PathBuf::from("overlapnesttest/lib/2021/new.jpg")]
+    );
+    let pairs: Vec<(&Path, &Path)> = report
+        .overlaps
+        .iter()
+        .map(|o| (o.first.as_path(), o.second.as_path()))
+        .collect();
+    assert!(pairs.contains(&(
+        Path::new("overlapnesttest/copy/2020"),
+        Path::new("overlapnesttest/lib/2020")
+    )));
+    assert!(pairs.contains(&(
+        Path::new("overlapnesttest/copy/2021"),
+        Path::new("overlapnesttest/lib/2021")
+    )));
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("overlapnesttest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn dedupe() {
+    use dedupe::{DedupeOptions, KeepPolicy};
+    use std::os::unix::fs::MetadataExt;
+
+    for dir in &["dedupetest/a/b", "dedupetest/c"] {
+        Command::new("mkdir").arg("-p").arg(dir).output().unwrap();
+    }
+    std::fs::write("dedupetest/a/b/deep", b"content").unwrap();
+    std::fs::write("dedupetest/a/shallow", b"content").unwrap();
+    std::fs::write("dedupetest/c/preferred", b"content").unwrap();
+
+    let i = scan("dedupetest");
+    let groups = i.duplicate_report().groups;
+    assert_eq!(groups.len(), 1);
+
+    // A dry run changes nothing
+    let mut log = vec![];
+    let outcome = dedupe::dedupe(&groups, &DedupeOptions::default(), &mut log).unwrap();
+    assert_eq!(outcome.actions.len(), 2);
+    assert_eq!(outcome.saved(), 14);
+    assert!(outcome
+        .actions
+        .iter()
+        .all(|a| a.kept == Path::new("dedupetest/a/shallow")));
+    assert!(log.is_empty());
+    assert_eq!(fs::metadata("dedupetest/a/shallow").unwrap().nlink(), 1);
+
+    // Without a preferred copy, --keep decides
+    Command::new("touch")
+        .arg("-d")
+        .arg("@2000000000")
+        .arg("dedupetest/a/b/deep")
+        .output()
+        .unwrap();
+    let options = DedupeOptions {
+        keep: KeepPolicy::Newest,
+        prefer: vec![PathBuf::from("dedupetest/missing")],
+        ..Default::default()
+    };
+    let outcome = dedupe::dedupe(&groups, &options, &mut log).unwrap();
+    assert!(outcome
+        .actions
+        .iter()
+        .all(|a| a.kept == Path::new("dedupetest/a/b/deep")));
+
+    // Files changed since the scan are left alone
+    std::fs::write("dedupetest/a/b/deep", b"changed").unwrap();
+    let options = DedupeOptions {
+        keep: KeepPolicy::Newest,
+        prefer: vec![PathBuf::from("dedupetest/c")],
+        dry_run: false,
+        ..Default::default()
+    };
+    let outcome = dedupe::dedupe(&groups, &options, &mut log).unwrap();
+    assert_eq!(outcome.actions.len(), 1);
+    assert_eq!(outcome.actions[0].kept, Path::new("dedupetest/c/preferred"));
+    assert_eq!(outcome.skipped[0].1, "content differs");
+    assert_eq!(fs::metadata("dedupetest/c/preferred").unwrap().nlink(), 2);
+    assert_eq!(std::fs::read("dedupetest/a/shallow").unwrap(), b"content");
+
+    // Already linked files are skipped on a second run
+    let outcome = dedupe::dedupe(&groups, &options, &mut log).unwrap();
+    assert!(outcome.actions.is_empty());
+
+    assert_eq!(dedupe::undo(log.as_slice()).unwrap(), 1);
+    assert_eq!(fs::metadata("dedupetest/c/preferred").unwrap().nlink(), 1);
+    assert_eq!(std::fs::read("dedupetest/a/shallow").unwrap(), b"content");
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("dedupetest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn dedupe_undo_log() {
+    use dedupe::DedupeOptions;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("dedupelogtest")
+        .output()
+        .unwrap();
+    let odd = Path::new(std::ffi::OsStr::from_bytes(b"dedupelogtest/copy\xff"));
+    std::fs::write("dedupelogtest/a", b"content").unwrap();
+    std::fs::write(odd, b"content").unwrap();
+    Command::new("touch")
+        .arg("-d")
+        .arg("@1000000000")
+        .arg(odd)
+        .output()
+        .unwrap();
+
+    let groups = scan("dedupelogtest").duplicate_report().groups;
+    let options = DedupeOptions {
+        dry_run: false,
+        ..Default::default()
+    };
+    let mut log = vec![];
+    let outcome = dedupe::dedupe(&groups, &options, &mut log).unwrap();
+    assert_eq!(outcome.actions.len(), 1);
+    assert_eq!(outcome.actions[0].replaced, odd);
+
+    // Absolute paths, with names that are not UTF-8 kept as bytes
+    let entry: serde_json::Value = serde_json::from_slice(&log).unwrap();
+    assert!(Path::new(entry["kept"].as_str().unwrap()).is_absolute());
+    let replaced: Vec<u8> = entry["replaced"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|b| b.as_u64().unwrap() as u8)
+        .collect();
+    assert_eq!(
+        Path::new(std::ffi::OsStr::from_bytes(&replaced)),
+        fs::canonicalize(odd).unwrap()
+    );
+
+    assert_eq!(dedupe::undo(log.as_slice()).unwrap(), 1);
+    let meta = fs::metadata(odd).unwrap();
+    assert_eq!(meta.nlink(), 1);
+    assert_eq!(meta.mtime(), 1000000000);
+    assert_eq!(std::fs::read(odd).unwrap(), b"content");
+
+    // Nothing is replaced without an entry in the log
+    struct Broken;
+    impl std::io::Write for Broken {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("disk full"))
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let outcome = dedupe::dedupe(&groups, &options, Broken).unwrap();
+    assert!(outcome.actions.is_empty());
+    assert_eq!(outcome.skipped.len(), 1);
+    assert_eq!(fs::metadata(odd).unwrap().nlink(), 1);
+
+    // Entries marked as not applied are left alone
+    let mut marked = log.clone();
+    let marker = serde_json::json!({"not_applied": true, "replaced": entry["replaced"]});
+    marked.extend(format!("{}\n", marker).bytes());
+    std::fs::write(odd, b"changed").unwrap();
+    assert_eq!(dedupe::undo(marked.as_slice()).unwrap(), 0);
+    assert_eq!(std::fs::read(odd).unwrap(), b"changed");
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("dedupelogtest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn trash() {
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("trashtest/a/b")
+        .output()
+        .unwrap();
+    std::fs::write("trashtest/a/b/one.txt", b"12345").unwrap();
+    std::fs::write("trashtest/a/two.txt", b"123").unwrap();
+    std::fs::write("trashtest/b", b"1").unwrap();
+    let xdg = std::env::current_dir().unwrap().join("trashtest_xdg");
+
+    let mut i = scan("trashtest");
+    let trashed = i.trash_in(Path::new("trashtest/a/b"), &xdg).unwrap();
+    assert_eq!(trashed, xdg.join("Trash/files/b"));
+    assert!(trashed.join("one.txt").is_file());
+    assert!(!Path::new("trashtest/a/b").exists());
+    let trashinfo = std::fs::read_to_string(xdg.join("Trash/info/b.trashinfo")).unwrap();
+    info!("{}", trashinfo);
+    let expected = format!(
+        "[Trash Info]\nPath={}/trashtest/a/b\nDeletionDate=",
+        std::env::current_dir().unwrap().display()
+    );
+    assert!(trashinfo.starts_with(&expected));
+    assert_eq!(trashinfo.lines().last().unwrap().len(), 32);
+
+    assert!(!i.tree.contains_key(Path::new("trashtest/a/b")));
+    assert_eq!(i.tree[Path::new("trashtest/a")].combined_size, 3);
+    assert!(i.tree[Path::new("trashtest/a")].directories.is_empty());
+    assert_eq!(i.tree[Path::new("trashtest")].combined_size, 4);
+    assert_eq!(i.combined_size, 4);
+    assert_eq!(i.files.len(), 2);
+    assert_eq!(i.filetypes["txt"].size, 3);
+
+    // Names already in the trash get a number
+    let trashed = i.trash_in(Path::new("trashtest/b"), &xdg).unwrap();
+    assert_eq!(trashed, xdg.join("Trash/files/b.2"));
+    assert!(xdg.join("Trash/info/b.2.trashinfo").is_file());
+    assert_eq!(i.combined_size, 3);
+
+    // A symlink is trashed from the filesystem it is on, not the one of its target
+    #[cfg(target_os = "linux")]
+    {
+        std::os::unix::fs::symlink("/dev/null", "trashtest/null").unwrap();
+        let all = mounts::mounts();
+        assert_eq!(
+            mounts::mount_of_link("trashtest/null", &all).map(|m| &m.mount_point),
+            mounts::mount_of("trashtest", &all).map(|m| &m.mount_point)
+        );
+        let trashed = trash::trash_in(Path::new("trashtest/null"), &xdg).unwrap();
+        assert_eq!(trashed, xdg.join("Trash/files/null"));
+        assert!(Path::new("/dev/null").exists());
+    }
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("trashtest")
+        .arg("trashtest_xdg")
+        .output()
+        .unwrap();
+}
+
+/// Check that a DirInfo changed in place matches a fresh scan of its root
+fn assert_same_as_scan(info: &DirInfo) {
+    let fresh = scan(&info.root);
+    let sorted_paths = |files: &[File]| {
+        let mut paths: Vec<PathBuf> = files.iter().map(|f| f.path.clone()).collect();
+        paths.sort();
+        paths
+    };
+
+    assert_eq!(info.combined_size, fresh.combined_size);
+    assert_eq!(info.combined_allocated_size, fresh.combined_allocated_size);
+    assert_eq!(sorted_paths(&info.files), sorted_paths(&fresh.files));
+
+    let mut keys: Vec<_> = info.tree.keys().collect();
+    let mut fresh_keys: Vec<_> = fresh.tree.keys().collect();
+    keys.sort();
+    fresh_keys.sort();
+    assert_eq!(keys, fresh_keys);
+    for (path, dir) in &fresh.tree {
+        let changed = &info.tree[path];
+        assert_eq!(changed.size, dir.size, "{:?}", path);
+        assert_eq!(changed.combined_size, dir.combined_size, "{:?}", path);
+        assert_eq!(changed.allocated_size, dir.allocated_size, "{:?}", path);
+        assert_eq!(
+            changed.combined_allocated_size, dir.combined_allocated_size,
+            "{:?}",
+            path
+        );
+        assert_eq!(changed.parent, dir.parent, "{:?}", path);
+        assert_eq!(sorted_paths(&changed.files), sorted_paths(&dir.files));
+        let mut dirs = changed.directories.clone();
+        let mut fresh_dirs = dir.directories.clone();
+        dirs.sort();
+        fresh_dirs.sort();
+        let mut unique = fresh_dirs.clone();
+        unique.dedup();
+        assert_eq!(unique, fresh_dirs, "listed twice in {:?}", path);
+        assert_eq!(dirs, fresh_dirs, "{:?}", path);
+    }
+
+    let mut exts: Vec<_> = info.filetypes.keys().collect();
+    let mut fresh_exts: Vec<_> = fresh.filetypes.keys().collect();
+    exts.sort();
+    fresh_exts.sort();
+    assert_eq!(exts, fresh_exts);
+    for (ext, ftype) in &fresh.filetypes {
+        assert_eq!(info.filetypes[ext].size, ftype.size);
+        assert_eq!(
+            sorted_paths(&info.filetypes[ext].files),
+            sorted_paths(&ftype.files)
+        );
+    }
+
+    let sizes = |files: &[File]| files.iter().map(|f| f.size).collect::<Vec<_>>();
+    assert_eq!(sizes(&info.files_by_size), sizes(&fresh.files_by_size));
+    let type_sizes = |types: &[FileType]| types.iter().map(|t| t.size).collect::<Vec<_>>();
+    assert_eq!(
+        type_sizes(&info.types_by_size),
+        type_sizes(&fresh.types_by_size)
+    );
+    let dir_sizes = |dirs: &[Directory]| dirs.iter().map(|d| d.size).collect::<Vec<_>>();
+    assert_eq!(
+        dir_sizes(&info.dirs_by_size),
+        dir_sizes(&fresh.dirs_by_size)
+    );
+
+    let mut hashes: Vec<_> = info.duplicates.keys().collect();
+    let mut fresh_hashes: Vec<_> = fresh.duplicates.keys().collect();
+    hashes.sort();
+    fresh_hashes.sort();
+    assert_eq!(hashes, fresh_hashes);
+    for (hash, files) in &fresh.duplicates {
+        assert_eq!(sorted_paths(&info.duplicates[hash]), sorted_paths(files));
+    }
+}
+
+#[test]
+fn mutations() {
+    for dir in &["mutatetest/a/b/deep", "mutatetest/c", "mutatetest/empty"] {
+        Command::new("mkdir").arg("-p").arg(dir).output().unwrap();
+    }
+    std::fs::write("mutatetest/a/b/one.txt", b"12345").unwrap();
+    std::fs::write("mutatetest/a/two.rs", b"123").unwrap();
+    std::fs::write("mutatetest/a/three.rs", b"1234567").unwrap();
+    std::fs::write("mutatetest/c/dup1", b"same").unwrap();
+    std::fs::write("mutatetest/dup2", b"same").unwrap();
+
+    let mut i = scan("mutatetest");
+    assert_same_as_scan(&i);
+
+    std::fs::remove_file("mutatetest/a/two.rs").unwrap();
+    assert_eq!(i.remove_path(Path::new("mutatetest/a/two.rs")).len(), 1);
+    assert_same_as_scan(&i);
+
+    std::fs::remove_dir_all("mutatetest/c").unwrap();
+    i.remove_path(Path::new("mutatetest/c"));
+    assert!(i.duplicates.is_empty());
+    assert_same_as_scan(&i);
+
+    std::fs::write("mutatetest/a/new.md", b"same").unwrap();
+    i.add_file(File::from_path("mutatetest/a/new.md").unwrap())
+        .unwrap();
+    assert_eq!(i.duplicates.len(), 1);
+    assert_same_as_scan(&i);
+
+    // Adding a known file again replaces it
+    std::fs::write("mutatetest/a/new.md", b"changed").unwrap();
+    i.add_file(File::from_path("mutatetest/a/new.md").unwrap())
+        .unwrap();
+    assert!(i.duplicates.is_empty());
+    assert_same_as_scan(&i);
+    assert!(i.add_file(File::from_path("Cargo.toml").unwrap()).is_err());
+
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("mutatetest/x/y")
+        .output()
+        .unwrap();
+    std::fs::write("mutatetest/x/y/z.txt", b"z").unwrap();
+    i.add_file(File::from_path("mutatetest/x/y/z.txt").unwrap())
+        .unwrap();
+    assert_same_as_scan(&i);
+
+    std::fs::rename("mutatetest/a", "mutatetest/empty/moved").unwrap();
+    i.tree.get_mut(Path::new("mutatetest/a/b")).unwrap().mount = Some("ext4".into());
+    i.move_path(
+        Path::new("mutatetest/a"),
+        Path::new("mutatetest/empty/moved"),
+    )
+    .unwrap();
+    assert_same_as_scan(&i);
+    assert_eq!(
+        i.tree[Path::new("mutatetest/empty/moved/b")]
+            .mount
+            .as_deref(),
+        Some("ext4")
+    );
+    assert!(i
+        .move_path(Path::new("mutatetest/x"), Path::new("elsewhere/x"))
+        .is_err());
+    assert!(i.tree.contains_key(Path::new("mutatetest/x")));
+
+    std::fs::rename("mutatetest/dup2", "mutatetest/x/dup2.txt").unwrap();
+    i.move_path(
+        Path::new("mutatetest/dup2"),
+        Path::new("mutatetest/x/dup2.txt"),
+    )
+    .unwrap();
+    assert_eq!(i.filetypes["txt"].files.len(), 3);
+    assert_same_as_scan(&i);
+
+    std::fs::remove_dir_all("mutatetest/empty/moved/b/deep").unwrap();
+    i.remove_path(Path::new("mutatetest/empty/moved/b/deep"));
+    assert_same_as_scan(&i);
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("mutatetest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn junk() {
+    use junk::{Confidence, DirectoryRule, JunkDetector};
+
+    for dir in &[
+        "junktest/crate/target/debug",
+        "junktest/notacrate/target",
+        "junktest/web/node_modules/left-pad/node_modules/inner",
+        "junktest/loose/node_modules",
+        "junktest/py/__pycache__",
+        "junktest/big",
+    ] {
+        Command::new("mkdir").arg("-p").arg(dir).output().unwrap();
+    }
+    std::fs::write("junktest/crate/Cargo.toml", b"[package]").unwrap();
+    std::fs::write("junktest/crate/target/debug/app", vec![0; 1000]).unwrap();
+    std::fs::write("junktest/notacrate/target/keep", vec![0; 10]).unwrap();
+    std::fs::write("junktest/web/package.json", b"{}").unwrap();
+    std::fs::write("junktest/web/node_modules/left-pad/index.js", vec![0; 300]).unwrap();
+    std::fs::write(
+        "junktest/web/node_modules/left-pad/node_modules/inner/index.js",
+        vec![0; 200],
+    )
+    .unwrap();
+    std::fs::write("junktest/loose/node_modules/x.js", vec![0; 50]).unwrap();
+    std::fs::write("junktest/py/__pycache__/mod.pyc", vec![0; 20]).unwrap();
+    std::fs::write("junktest/py/.mod.py.swp", vec![0; 5]).unwrap();
+    std::fs::write("junktest/big/data.bin", vec![0; 700]).unwrap();
+
+    let i = scan("junktest");
+    let found = i.junk();
+    info!("{:#?}", found);
+    let summary: Vec<(&str, u64, Confidence)> = found
+        .iter()
+        .map(|m| (m.path.to_str().unwrap(), m.size, m.confidence))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("junktest/crate/target", 1000, Confidence::High),
+            ("junktest/web/node_modules", 500, Confidence::High),
+            ("junktest/loose/node_modules", 50, Confidence::Medium),
+            ("junktest/py/__pycache__", 20, Confidence::High),
+            ("junktest/py/.mod.py.swp", 5, Confidence::Low),
+        ]
+    );
+    assert_eq!(found[0].regenerate.as_deref(), Some("cargo build"));
+
+    // Custom rules
+    let mut detector = JunkDetector::empty();
+    detector
+        .add(DirectoryRule::new("Big data", "big", Confidence::Low).with_regenerate("make data"));
+    let found = detector.detect(&i);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].size, 700);
+    assert_eq!(found[0].rule, "Big data");
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("junktest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn cachedir_tags() {
+    use cachedir::{CacheDirs, SIGNATURE, TAG_NAME};
+
+    for dir in &[
+        "cachedirtest/cache/inner",
+        "cachedirtest/fake",
+        "cachedirtest/data",
+    ] {
+        Command::new("mkdir").arg("-p").arg(dir).output().unwrap();
+    }
+    let mut tag = SIGNATURE.to_vec();
+    tag.extend_from_slice(b"\n# This file is a cache directory tag.\n");
+    std::fs::write(Path::new("cachedirtest/cache").join(TAG_NAME), &tag).unwrap();
+    std::fs::write("cachedirtest/cache/inner/blob", vec![0; 1000]).unwrap();
+    // A tag without the signature does not count
+    std::fs::write(
+        Path::new("cachedirtest/fake").join(TAG_NAME),
+        b"Signature: wrong",
+    )
+    .unwrap();
+    std::fs::write("cachedirtest/data/keep", vec![0; 10]).unwrap();
+
+    let scan_with = |cache_dirs| {
+        let options = ScanOptions {
+            cache_dirs,
+            ..Default::default()
+        };
+        scan_with_options("cachedirtest", &options, |_| {}, u128::MAX)
+    };
+
+    let ignored = scan_with(CacheDirs::Ignore);
+    assert!(ignored.cache_dirs().is_empty());
+    assert_eq!(ignored.cache_size(), 0);
+
+    let labelled = scan_with(CacheDirs::Label);
+    let caches: Vec<&Path> = labelled
+        .cache_dirs()
+        .iter()
+        .map(|d| d.path.as_path())
+        .collect();
+    assert_eq!(caches, vec![Path::new("cachedirtest/cache")]);
+    assert_eq!(labelled.cache_size(), 1000 + tag.len() as u64);
+    assert_eq!(labelled.combined_size, ignored.combined_size);
+
+    let excluded = scan_with(CacheDirs::Exclude);
+    assert_eq!(
+        excluded.skipped,
+        vec![(PathBuf::from("cachedirtest/cache"), SkipReason::CacheDir)]
+    );
+    assert!(!excluded.tree.contains_key(Path::new("cachedirtest/cache")));
+    assert_eq!(
+        excluded.combined_size,
+        ignored.combined_size - labelled.cache_size()
+    );
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("cachedirtest")
+        .output()
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn cachedir_symlink_among_siblings() {
+    use cachedir::{CacheDirs, SIGNATURE, TAG_NAME};
+
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("cachelinktest/real")
+        .arg("cachelinktest/dir")
+        .output()
+        .unwrap();
+    std::fs::write(Path::new("cachelinktest/real").join(TAG_NAME), SIGNATURE).unwrap();
+    for i in 0..50 {
+        std::fs::write(format!("cachelinktest/dir/file{}", i), vec![0; 1000]).unwrap();
+    }
+    std::os::unix::fs::symlink("../real", "cachelinktest/dir/clink").unwrap();
+
+    let options = ScanOptions {
+        cache_dirs: CacheDirs::Exclude,
+        ..Default::default()
+    };
+    let info = scan_with_options("cachelinktest/dir", &options, |_| {}, u128::MAX);
+    assert!(info.skipped.is_empty());
+    assert_eq!(info.files.len(), 50);
+    assert_eq!(info.combined_size, 50000);
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("cachelinktest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn exclude_rules() {
+    use exclude::{Pattern, DEFAULT_IGNORE_FILES};
+
+    let matches =
+        |glob: &str, path: &str, is_dir: bool| Pattern::new(glob).matches(Path::new(path), is_dir);
+    assert!(matches("*.o", "a/b/main.o", false));
+    assert!(!matches("*.o", "a/b/main.c", false));
+    assert!(matches("build/", "a/build", true));
+    assert!(!matches("build/", "a/build", false));
+    assert!(matches("/build", "build", true));
+    assert!(!matches("/build", "a/build", true));
+    assert!(matches("a/**/c", "a/c", true));
+    assert!(matches("a/**/c", "a/b1/b2/c", true));
+    assert!(!matches("a/*/c", "a/b1/b2/c", true));
+    assert!(matches("img[0-9].png", "img3.png", false));
+    assert!(!matches("img[!0-9].png", "img3.png", false));
+    assert!(matches("\\#notes", "#notes", false));
+
+    for dir in &[
+        "excludetest/.git/objects",
+        "excludetest/src/gen",
+        "excludetest/logs",
+    ] {
+        Command::new("mkdir").arg("-p").arg(dir).output().unwrap();
+    }
+    std::fs::write("excludetest/.git/objects/pack", vec![0; 100]).unwrap();
+    std::fs::write("excludetest/src/main.rs", vec![0; 10]).unwrap();
+    std::fs::write("excludetest/src/gen/out.rs", vec![0; 20]).unwrap();
+    std::fs::write("excludetest/src/keep.log", vec![0; 30]).unwrap();
+    std::fs::write("excludetest/logs/a.log", vec![0; 40]).unwrap();
+    std::fs::write("excludetest/.gitignore", "*.log\n").unwrap();
+    std::fs::write("excludetest/src/.ignore", "gen/\n!keep.log\n").unwrap();
+
+    let scan_with =
+        |options: ScanOptions| scan_with_options("excludetest", &options, |_| {}, u128::MAX);
+
+    let all = scan_with(ScanOptions::default());
+    assert!(all.skipped.is_empty());
+    assert_eq!(all.combined_size, 100 + 10 + 20 + 30 + 40 + 6 + 15);
+
+    let excluded = scan_with(ScanOptions {
+        exclude: vec![".git".to_string()],
+        ignore_files: DEFAULT_IGNORE_FILES.iter().map(|s| s.to_string()).collect(),
+        ..Default::default()
+    });
+    let mut skipped = excluded.skipped.clone();
+    skipped.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        skipped,
+        vec![
+            (
+                PathBuf::from("excludetest/.git"),
+                SkipReason::Excluded(".git".to_string())
+            ),
+            (
+                PathBuf::from("excludetest/logs/a.log"),
+                SkipReason::Ignored(PathBuf::from("excludetest/.gitignore"))
+            ),
+            (
+                PathBuf::from("excludetest/src/gen"),
+                SkipReason::Ignored(PathBuf::from("excludetest/src/.ignore"))
+            ),
+        ]
+    );
+    // The negation in the deeper ignore file wins over the .gitignore above it
+    assert_eq!(excluded.combined_size, 10 + 30 + 6 + 15);
+    assert!(!excluded.tree.contains_key(Path::new("excludetest/.git")));
+
+    let included = scan_with(ScanOptions {
+        include: vec!["*.rs".to_string(), "logs/".to_string()],
+        ..Default::default()
+    });
+    let included_files = included.files_by_size();
+    let mut files: Vec<&Path> = included_files.iter().map(|f| f.path.as_path()).collect();
+    files.sort();
+    assert_eq!(
+        files,
+        vec![
+            Path::new("excludetest/logs/a.log"),
+            Path::new("excludetest/src/gen/out.rs"),
+            Path::new("excludetest/src/main.rs"),
+        ]
+    );
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("excludetest")
+        .output()
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn exclude_symlink_among_siblings() {
+    Command::new("mkdir")
+        .arg("-p")
+        .arg("excludelinktest/real")
+        .arg("excludelinktest/dir")
+        .output()
+        .unwrap();
+    for i in 0..50 {
+        std::fs::write(format!("excludelinktest/dir/file{}", i), vec![0; 1000]).unwrap();
+    }
+    std::os::unix::fs::symlink("../real", "excludelinktest/dir/blink").unwrap();
+
+    let options = ScanOptions {
+        exclude: vec!["blink".to_string()],
+        ..Default::default()
+    };
+    let info = scan_with_options("excludelinktest", &options, |_| {}, u128::MAX);
+    assert_eq!(
+        info.skipped,
+        vec![(
+            PathBuf::from("excludelinktest/dir/blink"),
+            SkipReason::Excluded("blink".to_string())
+        )]
+    );
+    assert_eq!(info.files.len(), 50);
+    assert_eq!(info.combined_size, 50000);
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("excludelinktest")
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn filesystems() {
+    let proc = mounts::Mount::parse("22 1 0:21 / /proc rw,nosuid - proc proc rw").unwrap();
+    assert!(proc.is_pseudo());
+    // Memory filesystems often hold user data, such as /tmp
+    let shm = mounts::Mount::parse("25 1 0:23 / /dev/shm rw,nosuid - tmpfs tmpfs rw").unwrap();
+    assert!(!shm.is_pseudo());
+    let home = mounts::Mount::parse("30 1 8:1 / /home rw,relatime - ext4 /dev/sda1 rw").unwrap();
+    assert!(!home.is_pseudo());
+
+    let both = ScanOptions {
+        one_file_system: true,
+        skip_pseudo_filesystems: true,
+        ..Default::default()
+    };
+    let pseudo_only = ScanOptions {
+        skip_pseudo_filesystems: true,
+        ..Default::default()
+    };
+    assert_eq!(filesystem_skip(&both, Some(1), Some(1), None), None);
+    assert_eq!(
+        filesystem_skip(&both, Some(2), Some(1), Some(&home)),
+        Some(SkipReason::OtherFileSystem("ext4".to_string()))
+    );
+    assert_eq!(
+        filesystem_skip(&pseudo_only, Some(2), Some(1), Some(&proc)),
+        Some(SkipReason::PseudoFileSystem("proc".to_string()))
+    );
+    assert_eq!(
+        filesystem_skip(&pseudo_only, Some(2), Some(1), Some(&home)),
+        None
+    );
+    assert_eq!(
+        filesystem_skip(&Default::default(), Some(2), Some(1), Some(&proc)),
+        None
+    );
+
+    for dir in &["fstest/a", "fstest/b/c"] {
+        Command::new("mkdir").arg("-p").arg(dir).output().unwrap();
+    }
+    std::fs::write("fstest/a/file", vec![0; 10]).unwrap();
+    std::fs::write("fstest/b/file", vec![0; 20]).unwrap();
+    std::fs::write("fstest/b/c/file", vec![0; 40]).unwrap();
+
+    let plain = scan("fstest");
+    let options = ScanOptions {
+        one_file_system: true,
+        skip_pseudo_filesystems: true,
+        ..Default::default()
+    };
+    let mut info = scan_with_options("fstest", &options, |_| {}, u128::MAX);
+    assert!(info.skipped.is_empty());
+    assert_eq!(info.combined_size, plain.combined_size);
+    assert!(info
+        .tree
+        .values()
+        .all(|d| d.mount.is_none() || d.path == Path::new("fstest")));
+
+    // Pretend two directories are mount points
+    info.tree.get_mut(Path::new("fstest/b")).unwrap().mount = Some("nfs".to_string());
+    info.tree.get_mut(Path::new("fstest/b/c")).unwrap().mount = Some("ext4".to_string());
+    let usage: Vec<(PathBuf, Option<String>, u64)> = info
+        .filesystem_usage()
+        .into_iter()
+        .map(|u| (u.path, u.fs_type, u.size))
+        .collect();
+    assert_eq!(usage.len(), 3);
+    assert_eq!(
+        usage[0],
+        (PathBuf::from("fstest/b/c"), Some("ext4".to_string()), 40)
+    );
+    assert_eq!(
+        usage[1],
+        (PathBuf::from("fstest/b"), Some("nfs".to_string()), 20)
+    );
+    assert_eq!(usage[2].0, PathBuf::from("fstest"));
+    assert_eq!(usage[2].2, 10);
+
+    Command::new("rm")
+        .arg("-rf")
+        .arg("fstest")
+        .output()
+        .unwrap();
+}
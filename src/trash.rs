@@ -0,0 +1,306 @@
+//! Deletion to the trash, following the freedesktop.org trash specification.
+//!
+//! Files on the filesystem of the home directory go to `$XDG_DATA_HOME/Trash`. Files on other
+//! filesystems go to `$topdir/.Trash/$uid` if the administrator has set that up, or to
+//! `$topdir/.Trash-$uid`, so that trashing never copies data between filesystems.
+
+use crate::mounts::{mount_of_link, mounts};
+use crate::DirInfo;
+use anyhow::{anyhow, bail, Context, Result};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A trash directory, holding `files` and `info`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trash {
+    pub path: PathBuf,
+    /// Directory that paths in `.trashinfo` files are relative to. `None` for the home trash,
+    /// which records absolute paths.
+    pub top_dir: Option<PathBuf>,
+}
+
+impl Trash {
+    /// The trash in the user's home, which may not exist yet
+    pub fn home() -> Result<Trash> {
+        Ok(Trash::in_data_home(&data_home()?))
+    }
+
+    /// The home trash of a given data directory, usually `$XDG_DATA_HOME`
+    pub fn in_data_home(data_home: &Path) -> Trash {
+        Trash {
+            path: data_home.join("Trash"),
+            top_dir: None,
+        }
+    }
+
+    /// The trash to use for `path`, which must be absolute
+    pub fn for_path(path: &Path) -> Result<Trash> {
+        Trash::for_path_in(path, &data_home()?)
+    }
+
+    /// The trash to use for `path`, which must be absolute, with the home trash in `data_home`
+    pub fn for_path_in(path: &Path, data_home: &Path) -> Result<Trash> {
+        let home = Trash::in_data_home(data_home);
+        // The home trash may not exist yet, so its nearest existing ancestor decides the device.
+        // Files end up where a symlinked trash points to, so it is followed.
+        let home_device = home
+            .path
+            .ancestors()
+            .find_map(|a| fs::metadata(a).ok())
+            .map(|m| device(&m))
+            .ok_or_else(|| anyhow!("No existing directory above {}", home.path.display()))?;
+        // A symlink is moved itself, so it is looked up without following it
+        if device(&fs::symlink_metadata(path)?) == home_device {
+            return Ok(home);
+        }
+
+        let mounts = mounts();
+        let top_dir = mount_of_link(path, &mounts)
+            .map(|m| m.mount_point.clone())
+            .ok_or_else(|| anyhow!("No mount point found for {}", path.display()))?;
+        let uid = uid();
+        let admin = top_dir.join(".Trash");
+        if is_valid_admin_trash(&admin) {
+            return Ok(Trash {
+                path: admin.join(uid.to_string()),
+                top_dir: Some(top_dir),
+            });
+        }
+        Ok(Trash {
+            path: top_dir.join(format!(".Trash-{}", uid)),
+            top_dir: Some(top_dir),
+        })
+    }
+
+    /// Move a file or directory into this trash. Return its new path.
+    pub fn put(&self, path: &Path) -> Result<PathBuf> {
+        let files = self.path.join("files");
+        let info = self.path.join("info");
+        for dir in &[&files, &info] {
+            create_private_dir(dir)?;
+        }
+
+        let name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("Can not trash {}", path.display()))?;
+        let original = match &self.top_dir {
+            Some(top_dir) => path.strip_prefix(top_dir).unwrap_or(path),
+            None => path,
+        };
+        let content = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            percent_encode(original),
+            deletion_date(SystemTime::now())
+        );
+
+        // Creating the info file reserves the name, as the specification requires
+        for n in 1.. {
+            let mut trashed_name = name.to_os_string();
+            if n > 1 {
+                trashed_name.push(format!(".{}", n));
+            }
+            let mut info_name = trashed_name.clone();
+            info_name.push(".trashinfo");
+            let info_path = info.join(info_name);
+            let mut info_file = match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&info_path)
+            {
+                Ok(f) => f,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e).context(info_path.display().to_string()),
+            };
+            let trashed = files.join(trashed_name);
+            let moved = info_file
+                .write_all(content.as_bytes())
+                .and_then(|_| fs::rename(path, &trashed));
+            if let Err(e) = moved {
+                let _ = fs::remove_file(&info_path);
+                return Err(e).context(path.display().to_string());
+            }
+            return Ok(trashed);
+        }
+        unreachable!()
+    }
+}
+
+/// Move a file or directory to the trash of its filesystem. Return its new path.
+pub fn trash(path: &Path) -> Result<PathBuf> {
+    trash_in(path, &data_home()?)
+}
+
+/// Like `trash`, with the home trash in `data_home` instead of `$XDG_DATA_HOME`
+pub fn trash_in(path: &Path, data_home: &Path) -> Result<PathBuf> {
+    let path = absolute(path)?;
+    Trash::for_path_in(&path, data_home)?.put(&path)
+}
+
+impl DirInfo {
+    /// Move a file or directory to the trash and remove it from this DirInfo,
+    /// subtracting its size from all ancestors. Return its new path.
+    pub fn trash(&mut self, path: &Path) -> Result<PathBuf> {
+        self.trash_in(path, &data_home()?)
+    }
+
+    /// Like `trash`, with the home trash in `data_home` instead of `$XDG_DATA_HOME`
+    pub fn trash_in(&mut self, path: &Path, data_home: &Path) -> Result<PathBuf> {
+        let trashed = trash_in(path, data_home)?;
+        self.remove_path(path);
+        Ok(trashed)
+    }
+}
+
+/// `$XDG_DATA_HOME`, or its default below `$HOME`
+fn data_home() -> Result<PathBuf> {
+    Ok(match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME").ok_or_else(|| anyhow!("HOME is not set"))?)
+            .join(".local/share"),
+    })
+}
+
+/// Make a path absolute without resolving symlinks, which would trash the link target
+fn absolute(path: &Path) -> Result<PathBuf> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()?.join(path)
+    };
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    bail!("Invalid path {}", path.display());
+                }
+            }
+            c => normalized.push(c),
+        }
+    }
+    Ok(normalized)
+}
+
+/// Percent-encode a path for a `.trashinfo` file, like a URL path
+fn percent_encode(path: &Path) -> String {
+    #[cfg(unix)]
+    let bytes = {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    };
+    #[cfg(not(unix))]
+    let bytes = path.to_string_lossy().as_bytes().to_vec();
+    let mut out = String::new();
+    for b in bytes {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            b => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+/// Format a time as `YYYY-MM-DDThh:mm:ss`, in local time where it is known
+fn deletion_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    let secs = secs + utc_offset(secs);
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    // Civil date from days since the epoch, after Howard Hinnant's algorithm
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(target_os = "linux")]
+fn utc_offset(secs: i64) -> i64 {
+    let t = secs as libc::time_t;
+    // SAFETY: localtime_r only writes to the given struct
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&t, &mut tm).is_null() {
+            return 0;
+        }
+        tm.tm_gmtoff as i64
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn utc_offset(_secs: i64) -> i64 {
+    0
+}
+
+fn create_private_dir(dir: &Path) -> Result<()> {
+    if dir.is_dir() {
+        return Ok(());
+    }
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder
+        .create(dir)
+        .with_context(|| format!("Can not create {}", dir.display()))
+}
+
+/// `$topdir/.Trash` is only used if it is a real directory with the sticky bit set
+#[cfg(unix)]
+fn is_valid_admin_trash(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::symlink_metadata(path)
+        .map(|m| m.is_dir() && m.permissions().mode() & 0o1000 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_valid_admin_trash(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn device(meta: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.dev()
+}
+
+#[cfg(not(unix))]
+fn device(_meta: &fs::Metadata) -> u64 {
+    0
+}
+
+#[cfg(unix)]
+fn uid() -> u32 {
+    // SAFETY: geteuid has no preconditions and can not fail
+    unsafe { libc::geteuid() }
+}
+
+#[cfg(not(unix))]
+fn uid() -> u32 {
+    0
+}
@@ -0,0 +1,211 @@
+//! Renderer independent treemap layout, using the squarified algorithm
+//! by Bruls, Huizing and van Wijk.
+
+use crate::DirInfo;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+/// An axis aligned rectangle
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+impl Rect {
+    pub fn new(x: f64, y: f64, w: f64, h: f64) -> Rect {
+        Rect { x, y, w, h }
+    }
+
+    pub fn area(&self) -> f64 {
+        self.w * self.h
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What a treemap node stands for
+pub enum NodeKind {
+    Directory,
+    /// All files directly in a directory, like `Directory::files_as_fake_dir`
+    Files,
+    File,
+}
+
+#[derive(Debug, Clone)]
+/// A laid out node. Children are placed inside `rect`.
+pub struct TreemapNode {
+    /// Path of the directory or file. `Files` nodes carry the path of their directory.
+    pub path: PathBuf,
+    pub kind: NodeKind,
+    pub size: u64,
+    /// Levels below the root, which is at depth 0
+    pub depth: usize,
+    pub rect: Rect,
+    pub children: Vec<TreemapNode>,
+}
+
+impl TreemapNode {
+    /// Return this node and all descendants, parents before their children
+    pub fn flatten(&self) -> Vec<&TreemapNode> {
+        let mut nodes = vec![self];
+        for child in &self.children {
+            nodes.extend(child.flatten());
+        }
+        nodes
+    }
+}
+
+/// Lay out the tree below `root` in `rect`, down to `max_depth` levels.
+/// The files of each directory are grouped into a `Files` node next to its subdirectories.
+pub fn treemap(info: &DirInfo, root: &Path, rect: Rect, max_depth: usize) -> Option<TreemapNode> {
+    treemap_with_header(info, root, rect, max_depth, 0.0)
+}
+
+/// Like `treemap`, keeping a band of `header` at the top of each directory free of
+/// its children, so a label drawn there stays visible. Directories too small to hold
+/// the band twice are filled by their children.
+pub fn treemap_with_header(
+    info: &DirInfo,
+    root: &Path,
+    rect: Rect,
+    max_depth: usize,
+    header: f64,
+) -> Option<TreemapNode> {
+    let dir = info.tree.get(root)?;
+    let mut node = TreemapNode {
+        path: dir.path.clone(),
+        kind: NodeKind::Directory,
+        size: dir.combined_size,
+        depth: 0,
+        rect,
+        children: vec![],
+    };
+    layout_children(info, &mut node, max_depth, header);
+    Some(node)
+}
+
+fn layout_children(info: &DirInfo, node: &mut TreemapNode, max_depth: usize, header: f64) {
+    if node.depth >= max_depth {
+        return;
+    }
+    let children = children(info, &node.path, node.kind);
+    let sizes: Vec<u64> = children.iter().map(|c| c.2).collect();
+    let r = node.rect;
+    let inner = if node.kind == NodeKind::Directory && header > 0.0 && r.h > header * 2.0 {
+        Rect::new(r.x, r.y + header, r.w, r.h - header)
+    } else {
+        r
+    };
+    for ((path, kind, size), rect) in children.into_iter().zip(squarify(&sizes, inner)) {
+        let mut child = TreemapNode {
+            path,
+            kind,
+            size,
+            depth: node.depth + 1,
+            rect,
+            children: vec![],
+        };
+        layout_children(info, &mut child, max_depth, header);
+        node.children.push(child);
+    }
+}
+
+/// Return the children of a node, largest first, leaving out empty ones.
+/// Directories contain their subdirectories and a `Files` node, which contains the files.
+pub(crate) fn children(
+    info: &DirInfo,
+    path: &Path,
+    kind: NodeKind,
+) -> Vec<(PathBuf, NodeKind, u64)> {
+    let dir = match info.tree.get(path) {
+        Some(dir) => dir,
+        None => return vec![],
+    };
+    let mut children: Vec<(PathBuf, NodeKind, u64)> = match kind {
+        NodeKind::Directory => dir
+            .directories
+            .iter()
+            .filter_map(|d| info.tree.get(d))
+            .map(|d| (d.path.clone(), NodeKind::Directory, d.combined_size))
+            .chain(std::iter::once((
+                dir.path.clone(),
+                NodeKind::Files,
+                dir.size,
+            )))
+            .collect(),
+        NodeKind::Files => dir
+            .files
+            .iter()
+            .map(|f| (f.path.clone(), NodeKind::File, f.size))
+            .collect(),
+        NodeKind::File => vec![],
+    };
+    children.retain(|c| c.2 > 0);
+    children.sort_by_key(|c| std::cmp::Reverse(c.2));
+    children
+}
+
+/// Split `rect` into rectangles proportional to `sizes`, which must be sorted descending.
+pub fn squarify(sizes: &[u64], rect: Rect) -> Vec<Rect> {
+    let total: u64 = sizes.iter().sum();
+    let mut rects = vec![Rect::default(); sizes.len()];
+    if total == 0 || rect.area() <= 0.0 {
+        return rects;
+    }
+    let scale = rect.area() / total as f64;
+    let areas: Vec<f64> = sizes.iter().map(|s| *s as f64 * scale).collect();
+
+    let mut free = rect;
+    let mut start = 0;
+    while start < areas.len() {
+        let side = free.w.min(free.h);
+        // Grow the row as long as the aspect ratio of its worst rectangle improves
+        let mut end = start + 1;
+        while end < areas.len()
+            && worst(&areas[start..=end], side) <= worst(&areas[start..end], side)
+        {
+            end += 1;
+        }
+
+        let row = &areas[start..end];
+        let row_area: f64 = row.iter().sum();
+        let thickness = if side > 0.0 { row_area / side } else { 0.0 };
+        let mut offset = 0.0;
+        for (i, area) in row.iter().enumerate() {
+            let length = if thickness > 0.0 {
+                area / thickness
+            } else {
+                0.0
+            };
+            rects[start + i] = if free.w >= free.h {
+                Rect::new(free.x, free.y + offset, thickness, length)
+            } else {
+                Rect::new(free.x + offset, free.y, length, thickness)
+            };
+            offset += length;
+        }
+        if free.w >= free.h {
+            free.x += thickness;
+            free.w = (free.w - thickness).max(0.0);
+        } else {
+            free.y += thickness;
+            free.h = (free.h - thickness).max(0.0);
+        }
+        start = end;
+    }
+    rects
+}
+
+/// The worst aspect ratio in a row laid out along a side of the given length
+fn worst(row: &[f64], side: f64) -> f64 {
+    let sum: f64 = row.iter().sum();
+    let max = row.iter().cloned().fold(f64::MIN, f64::max);
+    let min = row.iter().cloned().fold(f64::MAX, f64::min);
+    if sum <= 0.0 || min <= 0.0 {
+        return f64::MAX;
+    }
+    let side2 = side * side;
+    let sum2 = sum * sum;
+    (side2 * max / sum2).max(sum2 / (side2 * min))
+}